@@ -178,15 +178,16 @@ $opts = Get-ConfigProperty -Object $Config -PropertyName 'options'
 $excl = Get-ConfigProperty -Object $Config -PropertyName 'excludes'
 $ordr = Get-ConfigProperty -Object $Config -PropertyName 'order_files'
 $git = Get-ConfigProperty -Object $Config -PropertyName 'git'
+$logs = Get-ConfigProperty -Object $Config -PropertyName 'logging'
 
-# Apply preference variables
+# Apply preference variables ([logging] since config version 2, [options] before)
 $Global:ctx_tag = $opts.tag ?? $opts.Tag ?? '>>= DOTS =<<'
 $Global:Verbosity = $opts.verbosity ?? $opts.Verbosity ?? 'Info'
-$Global:VerbosePreference = $opts.verbosePreference ?? $opts.VerbosePreference ?? 'SilentlyContinue'
-$Global:DebugPreference = $opts.debugPreference ?? $opts.DebugPreference ?? 'SilentlyContinue'
-$Global:InformationPreference = $opts.informationPreference ?? $opts.InformationPreference ?? 'SilentlyContinue'
-$Global:WarningPreference = $opts.warningPreference ?? $opts.WarningPreference ?? 'SilentlyContinue'
-$Global:ErrorActionPreference = $opts.errorActionPreference ?? $opts.ErrorActionPreference ?? 'Continue'
+$Global:VerbosePreference = $logs.verbose ?? $opts.verbosePreference ?? $opts.VerbosePreference ?? 'SilentlyContinue'
+$Global:DebugPreference = $logs.debug ?? $opts.debugPreference ?? $opts.DebugPreference ?? 'SilentlyContinue'
+$Global:InformationPreference = $logs.information ?? $opts.informationPreference ?? $opts.InformationPreference ?? 'SilentlyContinue'
+$Global:WarningPreference = $logs.warning ?? $opts.warningPreference ?? $opts.WarningPreference ?? 'SilentlyContinue'
+$Global:ErrorActionPreference = $logs.error_action ?? $opts.errorActionPreference ?? $opts.ErrorActionPreference ?? 'Continue'
 
 Write-Information "$ctx_tag Initializing PowerShell environment..."
 Write-Debug "$ctx_tag Verbosity: $Verbosity | Context: $ctx_tag"
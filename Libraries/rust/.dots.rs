@@ -19,23 +19,29 @@
 //! indicatif = "0.17"
 //! chrono = "0.4"
 //! clap_complete = "4.0"
+//! toml_edit = "0.22"
+//! schemars = "0.8"
+//! serde_yaml = "0.9"
 //! ```
 
 use anyhow::{Context, Result};
 use arboard::Clipboard;
-use chrono::Local;
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use chrono::{DateTime, Local};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     env, fs,
+    hash::{Hash, Hasher},
     io::{self, Write},
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    time::{Duration, SystemTime},
+    sync::LazyLock,
+    time::{Duration, Instant, SystemTime},
 };
 use walkdir::WalkDir;
 
@@ -59,6 +65,10 @@ struct Cli {
     #[arg(short, long, global = true)]
     quiet: bool,
 
+    /// Path to the dots repository (default: $DOTS, then search upward from here)
+    #[arg(long, global = true, value_name = "PATH")]
+    root: Option<PathBuf>,
+
     /// Icon style for output
     #[arg(long,alias = "icon",alias = "icon_style",global = true,value_enum,default_value_t = IconStyle::Nerdfont )]
     icons: IconStyle,
@@ -67,7 +77,11 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// List all configured hosts
-    Hosts,
+    Hosts {
+        /// Show as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Show detailed host information
     Info {
@@ -81,6 +95,27 @@ enum Commands {
         /// Show additional information (slower)
         #[arg(long, alias = "detail", alias = "details")]
         detailed: bool,
+
+        /// Extra attribute path under `config` or Nix expression to show (repeatable)
+        #[arg(long = "attr")]
+        attrs: Vec<String>,
+    },
+
+    /// Compare the evaluated configuration of two hosts
+    DiffHosts {
+        /// Host to compare from
+        host_a: String,
+
+        /// Host to compare to
+        host_b: String,
+
+        /// Option path under `config` to compare (repeatable)
+        #[arg(long = "attr")]
+        attrs: Vec<String>,
+
+        /// Show as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Rebuild configuration (add --execute to run it)
@@ -95,6 +130,12 @@ enum Commands {
         /// Show only the command
         #[arg(long)]
         command: bool,
+
+        #[command(flatten)]
+        deploy: DeployArgs,
+
+        #[command(flatten)]
+        dirty: DirtyArgs,
     },
 
     /// Test configuration (add --execute to run it)
@@ -105,6 +146,12 @@ enum Commands {
         /// Execute the command immediately
         #[arg(long)]
         execute: bool,
+
+        #[command(flatten)]
+        deploy: DeployArgs,
+
+        #[command(flatten)]
+        dirty: DirtyArgs,
     },
 
     /// Build boot configuration (add --execute to run it)
@@ -115,6 +162,41 @@ enum Commands {
         /// Execute the command immediately
         #[arg(long)]
         execute: bool,
+
+        #[command(flatten)]
+        deploy: DeployArgs,
+
+        #[command(flatten)]
+        dirty: DirtyArgs,
+    },
+
+    /// Build several hosts in parallel and summarize the results
+    Build {
+        /// Build every host in the flake
+        #[arg(long, conflicts_with = "hosts")]
+        all: bool,
+
+        /// Hosts to build, comma separated (default: current host)
+        #[arg(long, value_delimiter = ',')]
+        hosts: Vec<String>,
+
+        /// Maximum concurrent builds (default: options.jobs, or one per CPU)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Manage a Home Manager configuration (add --execute to run it)
+    Home {
+        /// Home Manager action
+        #[arg(value_enum, default_value_t = HomeAction::Switch)]
+        action: HomeAction,
+
+        /// Home configuration as user@host (default: current user and host)
+        target: Option<String>,
+
+        /// Execute the command immediately
+        #[arg(long)]
+        execute: bool,
     },
 
     /// Dry build configuration
@@ -252,6 +334,12 @@ enum Commands {
         action: CacheAction,
     },
 
+    /// Inspect and edit configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
     /// Generate shell completions
     Completions {
         /// Shell type
@@ -277,6 +365,26 @@ enum Commands {
     Rollback {
         #[arg(long)]
         execute: bool,
+
+        /// Switch to this generation instead of the previous one
+        #[arg(long, conflicts_with = "pick")]
+        to: Option<u32>,
+
+        /// Choose the generation from a list
+        #[arg(short, long)]
+        pick: bool,
+    },
+
+    /// List, compare and prune system generations
+    Generations {
+        #[command(subcommand)]
+        action: Option<GenerationsAction>,
+    },
+
+    /// Browse saved rebuild, test, boot and dry build logs
+    Logs {
+        #[command(subcommand)]
+        action: Option<LogsAction>,
     },
 
     /// Interactive menu for common operations
@@ -285,6 +393,17 @@ enum Commands {
     /// System health check
     Healthcheck,
 
+    /// Scaffold a commented .dots.toml from the repository
+    Init {
+        /// Overwrite an existing .dots.toml
+        #[arg(long)]
+        force: bool,
+
+        /// Print the generated config instead of writing it
+        #[arg(long)]
+        stdout: bool,
+    },
+
     /// Show enhanced help with examples
     Help,
 }
@@ -298,6 +417,340 @@ enum LogLevel {
     Error,
 }
 
+/// One generation of the NixOS system profile
+#[derive(Debug, Clone)]
+struct Generation {
+    number: u32,
+    date: DateTime<Local>,
+    current: bool,
+    path: PathBuf,
+    nixos_version: Option<String>,
+    kernel: Option<String>,
+}
+
+/// A saved build log and the metadata from its header
+#[derive(Debug, Clone)]
+struct BuildLog {
+    id: String,
+    path: PathBuf,
+    fields: HashMap<String, String>,
+}
+
+impl BuildLog {
+    /// Header or footer value, `-` when missing
+    fn field(&self, key: &str) -> &str {
+        self.fields.get(key).map_or("-", String::as_str)
+    }
+}
+
+/// The useful parts of a Nix error, parsed from its stderr
+#[derive(Debug, Clone, Default)]
+struct NixDiagnostic {
+    message: Vec<String>,
+    location: Option<(String, u32, u32)>,
+    source: Option<String>,
+    traces: Vec<String>,
+    derivation: Option<String>,
+    log_lines: Vec<String>,
+    log_hint: Option<String>,
+}
+
+impl NixDiagnostic {
+    /// Parse Nix stderr, mapping store copies of the flake back to repo-relative paths
+    fn parse(stderr: &str, root: &Path) -> Option<Self> {
+        let lines: Vec<&str> = stderr.lines().map(str::trim).collect();
+
+        //> A failed builder is the root cause; later errors only report its dependents
+        let start = lines
+            .iter()
+            .position(|line| line.starts_with("error: builder for"))
+            .or_else(|| {
+                lines
+                    .iter()
+                    .rposition(|line| line.starts_with("error:") && line.len() > "error:".len())
+            })?;
+
+        let mut diagnostic = NixDiagnostic::default();
+        for line in &lines[start..] {
+            let done = line.is_empty()
+                || line.starts_with("at ")
+                || line.starts_with('…')
+                || line.starts_with("last ")
+                || line.starts_with('>')
+                || line.starts_with("For full logs")
+                || (!diagnostic.message.is_empty() && line.starts_with("error:"));
+            if done || diagnostic.message.len() == 4 {
+                break;
+            }
+            diagnostic.message.push(line.to_string());
+        }
+
+        diagnostic.derivation = NIX_FAILED_BUILDER
+            .captures(lines[start])
+            .map(|captures| captures[1].to_string());
+
+        let mut frames: Vec<(usize, String, u32, u32)> = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if line.starts_with('…') || line.starts_with("while ") {
+                let trace = line.trim_start_matches('…').trim();
+                let trace = NIX_SOURCE_PATH.replace_all(trace, |captures: &regex::Captures| {
+                    Self::repo_relative(&captures[0], root)
+                });
+                diagnostic.traces.push(trace.to_string());
+            } else if line.starts_with("Did you mean") {
+                diagnostic.message.push(line.to_string());
+            } else if let Some(log) = line.strip_prefix('>') {
+                diagnostic.log_lines.push(log.trim().to_string());
+            } else if let Some(hint) = line.strip_prefix("For full logs, run ") {
+                diagnostic.log_hint =
+                    Some(hint.trim_matches(|c| c == '\'' || c == '.').to_string());
+            } else if let Some(captures) = NIX_FRAME.captures(line) {
+                frames.push((
+                    index,
+                    Self::repo_relative(&captures[1], root),
+                    captures[2].parse().unwrap_or(0),
+                    captures[3].parse().unwrap_or(0),
+                ));
+            }
+        }
+
+        //> The error's own frame if it is in the repo, else the repo frame nearest to it
+        let own = frames.iter().find(|(index, ..)| *index > start);
+        let chosen = own
+            .filter(|(_, file, ..)| !file.starts_with('/'))
+            .or_else(|| {
+                frames
+                    .iter()
+                    .filter(|(_, file, ..)| !file.starts_with('/'))
+                    .min_by_key(|(index, ..)| index.abs_diff(start))
+            })
+            .or(own);
+        if let Some((index, file, line_number, column)) = chosen {
+            diagnostic.source = lines[index + 1..]
+                .iter()
+                .take_while(|next| next.contains('|'))
+                .find(|next| next.starts_with(&format!("{}|", line_number)))
+                .and_then(|next| next.split_once('|'))
+                .map(|(_, code)| code.trim().to_string());
+            diagnostic.location = Some((file.clone(), *line_number, *column));
+        }
+
+        Some(diagnostic)
+    }
+
+    /// Helper: Path relative to the repo, following flake sources copied into the store
+    fn repo_relative(path: &str, root: &Path) -> String {
+        if let Ok(relative) = Path::new(path).strip_prefix(root) {
+            return relative.display().to_string();
+        }
+
+        let store_relative = path
+            .strip_prefix("/nix/store/")
+            .and_then(|rest| rest.split_once('/'))
+            .filter(|(name, _)| name.ends_with("-source"))
+            .map(|(_, relative)| relative);
+        match store_relative {
+            Some(relative) if root.join(relative).exists() => relative.to_string(),
+            _ => path.to_string(),
+        }
+    }
+
+    /// Compact report; `verbose` adds the evaluation trace
+    fn render(&self, verbose: bool) -> String {
+        let mut out = Vec::new();
+        let mut message = self.message.iter();
+        if let Some(first) = message.next() {
+            out.push(first.red().bold().to_string());
+        }
+        out.extend(message.map(|line| format!("  {}", line)));
+
+        if let Some((file, line, column)) = &self.location {
+            out.push(format!(
+                "  {} {}",
+                "-->".cyan(),
+                format!("{}:{}:{}", file, line, column).bold()
+            ));
+            if let Some(source) = &self.source {
+                out.push(format!(
+                    "  {} {}",
+                    format!("{:>4} |", line).dimmed(),
+                    source
+                ));
+            }
+        }
+        if let Some(derivation) = &self.derivation {
+            out.push(format!("  {} {}", "derivation:".dimmed(), derivation));
+        }
+        let skip = self
+            .log_lines
+            .len()
+            .saturating_sub(if verbose { usize::MAX } else { 5 });
+        for line in self.log_lines.iter().skip(skip) {
+            out.push(format!("  {} {}", ">".dimmed(), line));
+        }
+        if let Some(hint) = &self.log_hint {
+            out.push(format!("  {} {}", "full log:".dimmed(), hint));
+        }
+
+        if verbose {
+            for trace in &self.traces {
+                out.push(format!("  {} {}", "…".dimmed(), trace));
+            }
+        } else if !self.traces.is_empty() {
+            out.push(
+                format!(
+                    "  ({} trace frame(s) hidden, use --verbose)",
+                    self.traces.len()
+                )
+                .dimmed()
+                .to_string(),
+            );
+        }
+
+        out.join("\n")
+    }
+}
+
+/// Store paths in a closure (excluding its root) and its total size in bytes
+#[derive(Debug, Clone)]
+struct ClosureInfo {
+    paths: Vec<String>,
+    size: u64,
+}
+
+/// Outcome of one command run by `execute_parallel`
+#[derive(Debug, Clone)]
+struct ParallelResult {
+    description: String,
+    success: bool,
+    duration: Duration,
+    stdout: String,
+    stderr: String,
+}
+
+impl ParallelResult {
+    /// Most relevant line of a failure: the last `error:` line, else the last line
+    fn failure_reason(&self) -> String {
+        let lines: Vec<&str> = self
+            .stderr
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        lines
+            .iter()
+            .rev()
+            .find(|line| line.starts_with("error:"))
+            .or(lines.last())
+            .map(|line| line.to_string())
+            .unwrap_or_else(|| "failed without output".to_string())
+    }
+}
+
+/// Remote deployment flags shared by rebuild, test and boot
+#[derive(Args, Clone, Debug, Default)]
+struct DeployArgs {
+    /// Activate on this ssh destination instead of the local machine
+    #[arg(long)]
+    target_host: Option<String>,
+
+    /// Build on this ssh destination
+    #[arg(long)]
+    build_host: Option<String>,
+
+    /// Use sudo on the target host instead of logging in as root
+    #[arg(long)]
+    use_remote_sudo: bool,
+}
+
+/// Dirty working tree flags shared by rebuild, test and boot
+#[derive(Args, Clone, Debug, Default)]
+#[group(multiple = false)]
+struct DirtyArgs {
+    /// Build even if new .nix files are untracked
+    #[arg(long)]
+    allow_dirty: bool,
+
+    /// Stage untracked .nix files so the flake sees them
+    #[arg(long)]
+    stage_new: bool,
+
+    /// Commit all changes before building
+    #[arg(long)]
+    commit: bool,
+}
+
+/// What rebuild, test and boot do with uncommitted changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DirtyPolicy {
+    /// Warn, and ask before building without untracked .nix files
+    Warn,
+
+    /// Build as is
+    Allow,
+
+    /// Stage untracked .nix files first
+    Stage,
+
+    /// Commit everything first
+    Commit,
+}
+
+impl DirtyPolicy {
+    /// Parse a policy name case-insensitively, using `fallback` when unset or unknown
+    fn parse(value: &str, fallback: DirtyPolicy) -> DirtyPolicy {
+        match value.trim().to_lowercase().as_str() {
+            "warn" => DirtyPolicy::Warn,
+            "allow" | "allow-dirty" => DirtyPolicy::Allow,
+            "stage" | "stage-new" => DirtyPolicy::Stage,
+            "commit" => DirtyPolicy::Commit,
+            _ => fallback,
+        }
+    }
+}
+
+/// Home Manager actions for `dots home`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum HomeAction {
+    /// Build and activate the configuration
+    Switch,
+
+    /// Build the configuration without activating it
+    Build,
+
+    /// Show unread Home Manager news
+    News,
+}
+
+/// PowerShell-style action preference, as used by the `[logging]` options
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Preference {
+    /// Show the message and carry on
+    Continue,
+
+    /// Hide the message and carry on
+    SilentlyContinue,
+
+    /// Show the message and abort
+    Stop,
+
+    /// Show the message and ask whether to carry on
+    Inquire,
+}
+
+impl Preference {
+    /// Parse a preference name case-insensitively, using `fallback` when unset or unknown
+    fn parse(value: &str, fallback: Preference) -> Preference {
+        match value.trim().to_lowercase().as_str() {
+            "continue" => Preference::Continue,
+            "silentlycontinue" | "ignore" => Preference::SilentlyContinue,
+            "stop" => Preference::Stop,
+            "inquire" => Preference::Inquire,
+            _ => fallback,
+        }
+    }
+}
+
 struct ConsoleStyle<'a> {
     level: LogLevel,
     icon: Option<&'a str>,
@@ -441,18 +894,154 @@ enum CacheAction {
     List,
 }
 
+#[derive(Subcommand)]
+enum GenerationsAction {
+    /// List system generations
+    List {
+        /// Show as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compare the closures of two generations
+    Diff {
+        /// Older generation number
+        a: u32,
+
+        /// Newer generation number
+        b: u32,
+    },
+
+    /// Delete generations, e.g. `12`, `3-7` or `3,5,8-10`
+    Delete {
+        /// Generation numbers and ranges
+        range: String,
+
+        /// Execute the deletion
+        #[arg(long)]
+        execute: bool,
+    },
+
+    /// Delete all but the newest N generations (and the current one)
+    Keep {
+        /// Number of generations to keep
+        count: u32,
+
+        /// Execute the deletion
+        #[arg(long)]
+        execute: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogsAction {
+    /// List saved build logs
+    List {
+        /// Show only the newest N logs
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+
+    /// Print a build log by id (or unique part of one)
+    Show {
+        /// Log id, as shown by `dots logs list`
+        id: String,
+    },
+
+    /// Print the newest build log
+    Last,
+
+    /// Search all build logs for a pattern
+    Grep {
+        /// Regular expression
+        pattern: String,
+
+        /// Case insensitive search
+        #[arg(short, long)]
+        insensitive: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Show the effective configuration
+    Show {
+        /// Annotate each value with the file it came from
+        #[arg(long)]
+        origin: bool,
+
+        /// Show as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print a value by dotted key (e.g. git.auto_push)
+    Get {
+        /// Dotted key
+        key: String,
+    },
+
+    /// Set a value by dotted key in the repo config
+    Set {
+        /// Dotted key
+        key: String,
+
+        /// Value (parsed as TOML, falling back to a string)
+        value: String,
+
+        /// Write to the user config instead of the repo config
+        #[arg(long)]
+        user: bool,
+    },
+
+    /// Validate every configuration layer
+    Validate {
+        /// Also report unknown keys, type mismatches and invalid regexes
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Print the JSON Schema for configuration files
+    Schema {
+        /// Write the schema to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Upgrade config files to the current schema version
+    Migrate {
+        /// Only show the changes, failing if any file is out of date
+        #[arg(long)]
+        check: bool,
+
+        /// Rewrite without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Show where configuration is searched for and loaded from
+    Path,
+}
+
 /// Configuration structures
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 struct DotsConfig {
     #[serde(default = "default_name")]
     name: String,
 
+    /// Config schema version; older files are upgraded by `dots config migrate`
+    #[serde(default = "default_config_version")]
+    version: u32,
+
     #[serde(default)]
     git: GitConfig,
 
     #[serde(default)]
     options: Options,
 
+    #[serde(default)]
+    logging: Logging,
+
     #[serde(default)]
     experimental_features: ExperimentalFeatures,
 
@@ -470,13 +1059,30 @@ struct DotsConfig {
 
     #[serde(default)]
     aliases: HashMap<String, String>,
+
+    #[serde(default)]
+    merge: MergeOptions,
+
+    #[serde(default)]
+    info: InfoConfig,
+
+    /// Machine hostname to flake host name, matched case-insensitively
+    #[serde(default)]
+    hostnames: HashMap<String, String>,
+
+    #[serde(default)]
+    hosts: HashMap<String, HostProfile>,
 }
 
 fn default_name() -> String {
     "dotDots".to_string()
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+fn default_config_version() -> u32 {
+    CONFIG_VERSION
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
 struct GitConfig {
     #[serde(default = "default_git_user")]
     user: String,
@@ -495,14 +1101,14 @@ struct GitConfig {
 }
 
 fn default_git_user() -> String {
-    "craole-cc".to_string()
+    "".to_string()
 }
 
 fn default_git_email() -> String {
     "".to_string()
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
 struct Options {
     #[serde(default = "default_tag")]
     tag: String,
@@ -510,32 +1116,66 @@ struct Options {
     #[serde(default = "default_verbosity")]
     verbosity: String,
 
-    #[serde(default, rename = "verbosePreference")]
-    verbose_preference: String,
+    #[serde(default)]
+    auto_copy: bool,
 
-    #[serde(default, rename = "debugPreference")]
-    debug_preference: String,
+    #[serde(default)]
+    auto_confirm: bool,
 
-    #[serde(default, rename = "informationPreference")]
-    information_preference: String,
+    #[serde(default)]
+    color: bool,
 
-    #[serde(default, rename = "warningPreference")]
-    warning_preference: String,
+    #[serde(default)]
+    progress: bool,
 
-    #[serde(default, rename = "errorActionPreference")]
-    error_action_preference: String,
+    /// Concurrent builds for `dots build`, 0 for one per CPU
+    #[serde(default)]
+    jobs: usize,
 
+    /// Uncommitted changes before rebuild/test/boot: warn (default), allow, stage or commit
     #[serde(default)]
-    auto_copy: bool,
+    dirty_tree: String,
 
+    /// Desktop notifications when long operations finish
     #[serde(default)]
-    auto_confirm: bool,
+    notify: NotifyConfig,
+}
 
+/// Which finished operations raise a desktop notification, and after how long
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+struct NotifyConfig {
     #[serde(default)]
-    color: bool,
+    rebuild: bool,
 
     #[serde(default)]
-    progress: bool,
+    update: bool,
+
+    #[serde(default)]
+    check: bool,
+
+    /// `dots build`, including `--all`
+    #[serde(default)]
+    build: bool,
+
+    /// Skip operations that finished faster than this many seconds
+    #[serde(default = "default_notify_min_duration")]
+    min_duration: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            rebuild: false,
+            update: false,
+            check: false,
+            build: false,
+            min_duration: default_notify_min_duration(),
+        }
+    }
+}
+
+fn default_notify_min_duration() -> u64 {
+    30
 }
 
 fn default_tag() -> String {
@@ -546,19 +1186,35 @@ fn default_verbosity() -> String {
     "normal".to_string()
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
-struct ExperimentalFeatures {
+/// Preferences for each log level: Continue, SilentlyContinue, Stop or Inquire
+///
+/// Warnings and errors (`warning`, `error_action`, which also covers failed
+/// commands and hooks) can stop the operation or ask before it goes on.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct Logging {
     #[serde(default)]
-    enabled: Vec<String>,
+    verbose: String,
+
+    #[serde(default)]
+    debug: String,
 
     #[serde(default)]
-    nix_command: bool,
+    information: String,
 
     #[serde(default)]
-    flakes: bool,
+    warning: String,
+
+    #[serde(default)]
+    error_action: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct ExperimentalFeatures {
+    #[serde(default)]
+    enabled: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
 struct Excludes {
     #[serde(default)]
     patterns: Vec<String>,
@@ -570,7 +1226,7 @@ struct Excludes {
     files: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
 struct OrderFiles {
     #[serde(default)]
     filenames: Vec<String>,
@@ -579,7 +1235,7 @@ struct OrderFiles {
     priority: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 struct Include {
     path: String,
 
@@ -590,7 +1246,7 @@ struct Include {
     enabled: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 struct SubmoduleConfig {
     path: String,
 
@@ -610,7 +1266,7 @@ struct SubmoduleConfig {
     branch: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
 struct Hooks {
     #[serde(default)]
     pre_sync: Vec<String>,
@@ -629,13 +1285,273 @@ struct Hooks {
 
     #[serde(default)]
     post_update: Vec<String>,
+
+    #[serde(default)]
+    pre_home: Vec<String>,
+
+    #[serde(default)]
+    post_home: Vec<String>,
 }
 
-/// Main application state
-struct DotDots {
-    config: DotsConfig,
-    root: PathBuf,
-    cache_dir: PathBuf,
+/// Fields shown by `dots info`, on top of hostname, system, kernel and stateVersion
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct InfoConfig {
+    /// Field name to an attribute path under `config`, or a Nix expression
+    /// with `config` and `pkgs` in scope
+    #[serde(default)]
+    fields: HashMap<String, String>,
+
+    /// Desktop name to the option path that enables it
+    #[serde(default)]
+    desktops: HashMap<String, String>,
+}
+
+/// Per-host overrides from `[hosts.<name>]`, applied on top of the shared config
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct HostProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    options: Option<Options>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logging: Option<Logging>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hooks: Option<Hooks>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aliases: Option<HashMap<String, String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    experimental_features: Option<ExperimentalFeatures>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deploy: Option<DeployConfig>,
+}
+
+/// How `[hosts.<name>.deploy]` reaches a host that isn't this machine
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct DeployConfig {
+    /// SSH destination to activate on, e.g. `root@victus`
+    #[serde(default)]
+    target_host: Option<String>,
+
+    /// SSH destination to build on (default: this machine)
+    #[serde(default)]
+    build_host: Option<String>,
+
+    /// Use sudo on the target host instead of logging in as root
+    #[serde(default)]
+    use_remote_sudo: bool,
+}
+
+/// How vectors are combined when configuration layers are merged
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum VectorMerge {
+    /// A later layer replaces the whole vector
+    #[default]
+    Replace,
+
+    /// A later layer appends its items, skipping duplicates
+    Append,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+struct MergeOptions {
+    /// Default strategy for every vector
+    #[serde(default)]
+    vectors: VectorMerge,
+
+    /// Per-field strategy keyed by dotted path (e.g. "excludes.patterns")
+    #[serde(default)]
+    overrides: HashMap<String, VectorMerge>,
+}
+
+impl MergeOptions {
+    fn strategy_for(&self, path: &str) -> VectorMerge {
+        self.overrides.get(path).copied().unwrap_or(self.vectors)
+    }
+}
+
+/// Configuration files in the order they are searched within a directory
+const CONFIG_FILENAMES: [&str; 13] = [
+    ".dots.toml",
+    "dots.toml",
+    "config.toml",
+    ".config.toml",
+    ".config/dots.toml",
+    ".dots.json",
+    "dots.json",
+    ".dots.yaml",
+    "dots.yaml",
+    ".dots.yml",
+    "dots.yml",
+    ".dots.conf",
+    ".dotsrc",
+];
+
+/// Syntax of a configuration file, chosen by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+    /// `key = value` lines with optional `[section]` headers (`.dots.conf`, `.dotsrc`)
+    Rc,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Rc,
+        }
+    }
+}
+
+/// Sections a `[hosts.<name>]` profile may override
+const HOST_OVERRIDABLE: [&str; 5] = [
+    "options",
+    "logging",
+    "hooks",
+    "aliases",
+    "experimental_features",
+];
+
+/// Fields `dots info` always shows, so `info.fields` and `--attr` can't reuse them
+const INFO_BUILTIN_FIELDS: [&str; 5] = ["hostname", "system", "kernel", "stateVersion", "desktop"];
+
+/// Nix function turning an evaluated value into something `--json` can print
+///
+/// Derivations become their name, functions and failures become markers, and
+/// nesting stops at `depth`.
+const NIX_SANITIZE: &str = r#"depth: value:
+          let result = builtins.tryEval value; in
+          if !result.success then "<error>"
+          else if builtins.isFunction result.value then "<function>"
+          else if builtins.isPath result.value then toString result.value
+          else if builtins.isAttrs result.value then
+            if result.value ? outPath then result.value.name or "<derivation>"
+            else if depth == 0 then "<attrs>"
+            else builtins.mapAttrs (_: sanitize (depth - 1)) result.value
+          else if builtins.isList result.value then
+            if depth == 0 then "<list>" else map (sanitize (depth - 1)) result.value
+          else result.value"#;
+
+/// ANSI color escapes, stripped from tool output before it is shown or matched
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| Regex::new("\x1b\\[[0-9;]*m").unwrap());
+
+/// Directory holding one directory per host (`paths.api.hosts` in `default.nix`)
+const HOSTS_DIR: &str = "API/nix/hosts";
+
+/// Position line of a Nix error frame: `at /path/file.nix:12:5`
+static NIX_FRAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^at (/\S+?):(\d+):(\d+)").unwrap());
+
+/// A file inside a flake source copied into the store
+static NIX_SOURCE_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"/nix/store/[a-z0-9]{32}-source/[^\s'`:]+").unwrap());
+
+/// Name of the derivation whose builder failed
+static NIX_FAILED_BUILDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"builder for '/nix/store/[a-z0-9]{32}-(.+?)\.drv'").unwrap());
+
+/// NixOS system profile whose generations `dots generations` manages
+const SYSTEM_PROFILE: &str = "/nix/var/nix/profiles/system";
+
+/// Current config schema version, stamped into files by `dots config migrate`
+const CONFIG_VERSION: u32 = 2;
+
+/// Upgrades from older config schemas, as `(target version, description, planner)`
+///
+/// Each planner inspects a file at the previous version and returns the edits
+/// that bring it to the target version.
+const CONFIG_MIGRATIONS: [(u32, &str, MigrationPlanner); 1] = [(
+    2,
+    "move *Preference options into [logging] and feature flags into enabled",
+    DotDots::migrate_to_v2,
+)];
+
+/// Plans the edits for one migration step from the file as it stands
+type MigrationPlanner = fn(&serde_json::Value) -> Vec<MigrationOp>;
+
+/// A single edit made while migrating a config file
+#[derive(Debug, Clone)]
+enum MigrationOp {
+    /// Move a dotted key to another dotted key
+    Move(String, String),
+
+    /// Set a dotted key to a value
+    Set(String, serde_json::Value),
+
+    /// Remove a dotted key
+    Remove(String),
+}
+
+impl std::fmt::Display for MigrationOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationOp::Move(from, to) => write!(f, "move {} to {}", from, to),
+            MigrationOp::Set(key, value) => write!(f, "set {} = {}", key, value),
+            MigrationOp::Remove(key) => write!(f, "remove {}", key),
+        }
+    }
+}
+
+/// Origin of a configuration layer, from lowest to highest precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LayerKind {
+    Default,
+    User,
+    Repo,
+    Include,
+    Host,
+    Profile,
+    Environment,
+}
+
+impl LayerKind {
+    fn label(&self) -> &'static str {
+        match self {
+            LayerKind::Default => "default",
+            LayerKind::User => "user",
+            LayerKind::Repo => "repo",
+            LayerKind::Include => "include",
+            LayerKind::Host => "host",
+            LayerKind::Profile => "profile",
+            LayerKind::Environment => "env",
+        }
+    }
+}
+
+/// A single source of configuration before merging
+#[derive(Debug, Clone)]
+struct ConfigLayer {
+    kind: LayerKind,
+    path: Option<PathBuf>,
+    value: serde_json::Value,
+}
+
+impl ConfigLayer {
+    /// Where this layer came from, for provenance output
+    fn origin(&self) -> String {
+        match (&self.path, self.kind) {
+            (Some(path), _) => path.display().to_string(),
+            (None, LayerKind::Environment) => "environment".to_string(),
+            (None, LayerKind::Profile) => "host profile".to_string(),
+            (None, _) => "built-in default".to_string(),
+        }
+    }
+}
+
+/// Main application state
+struct DotDots {
+    config: DotsConfig,
+    config_layers: Vec<ConfigLayer>,
+    root: PathBuf,
+    cache_dir: PathBuf,
     logs_dir: PathBuf,
     tmp_dir: PathBuf,
     verbose: bool,
@@ -644,9 +1560,16 @@ struct DotDots {
 }
 
 impl DotDots {
-    fn new(verbose: bool, quiet: bool, icons: IconStyle) -> Result<Self> {
-        let dots_var = env::var("DOTS").context("DOTS environment variable not set")?;
-        let root = PathBuf::from(&dots_var);
+    fn new(
+        verbose: bool,
+        quiet: bool,
+        icons: IconStyle,
+        lenient_config: bool,
+        host: Option<&str>,
+        root: Option<&Path>,
+        bootstrap: bool,
+    ) -> Result<Self> {
+        let root = Self::find_root(root, bootstrap)?;
         let real_root = fs::canonicalize(&root).unwrap_or_else(|_| root.clone());
 
         let cache_base = real_root.join(".cache");
@@ -658,10 +1581,27 @@ impl DotDots {
             fs::create_dir_all(dir).context("Failed to create cache directory")?;
         }
 
-        let config = Self::load_config(&real_root)?;
+        let host_name = host
+            .map(String::from)
+            .unwrap_or_else(Self::get_current_host);
+        let (mut config, mut config_layers) =
+            Self::load_config(&real_root, &host_name, lenient_config)?;
+
+        //> DOTS_* variables override anything loaded from files
+        let loaded = serde_json::to_value(&config)?;
+        if let Some(layer) = Self::env_config_layer(&loaded, env::vars())? {
+            config_layers.push(layer);
+            config = Self::merge_config_layers(&config_layers)?;
+        }
+
+        //> logging.verbose = Continue behaves like passing --verbose
+        let verbose = verbose
+            || Preference::parse(&config.logging.verbose, Preference::SilentlyContinue)
+                == Preference::Continue;
 
         Ok(Self {
             config,
+            config_layers,
             root: real_root,
             cache_dir,
             logs_dir,
@@ -672,95 +1612,792 @@ impl DotDots {
         })
     }
 
-    fn load_config(root: &Path) -> Result<DotsConfig> {
-        let config_filenames = [
-            ".dots.toml",
-            "dots.toml",
-            "config.toml",
-            ".config.toml",
-            ".config/dots.toml",
-            ".dots.json",
-            "dots.json",
-            ".dots.conf",
-            ".dotsrc",
-        ];
+    /// Locate the dots repository: `--root`, then `$DOTS`, then the nearest
+    /// ancestor of the current directory with `flake.nix` and a dots config or
+    /// `.dotsroot` marker
+    ///
+    /// With `bootstrap` (for `dots init`, which creates the config), a git
+    /// toplevel or nearest ancestor with just `flake.nix` is accepted too.
+    fn find_root(explicit: Option<&Path>, bootstrap: bool) -> Result<PathBuf> {
+        if let Some(root) = explicit {
+            if !root.is_dir() {
+                anyhow::bail!("--root is not a directory: {}", root.display());
+            }
+            return Ok(root.to_path_buf());
+        }
+
+        if let Ok(dots_var) = env::var("DOTS") {
+            let root = PathBuf::from(&dots_var);
+            if root.is_dir() {
+                return Ok(root);
+            }
+            eprintln!("Ignoring DOTS={}: not a directory", dots_var);
+        }
+
+        let cwd = env::current_dir().context("Failed to read current directory")?;
+        let is_root = |dir: &Path| {
+            dir.join("flake.nix").is_file()
+                && (dir.join(".dotsroot").exists()
+                    || CONFIG_FILENAMES.iter().any(|name| dir.join(name).is_file()))
+        };
+
+        let found = cwd
+            .ancestors()
+            .find(|dir| is_root(dir))
+            .map(Path::to_path_buf);
+        let found = found.or_else(|| {
+            if !bootstrap {
+                return None;
+            }
+            let toplevel = Command::new("git")
+                .args(["rev-parse", "--show-toplevel"])
+                .current_dir(&cwd)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+                .filter(|dir| dir.join("flake.nix").is_file());
+            toplevel.or_else(|| {
+                cwd.ancestors()
+                    .find(|dir| dir.join("flake.nix").is_file())
+                    .map(Path::to_path_buf)
+            })
+        });
+
+        found.with_context(|| {
+            format!(
+                "No dots repository found from {}\n\
+                     Pass --root <path>, set DOTS, or run inside a directory \
+                     containing flake.nix and a .dots.toml (or .dotsroot marker)",
+                cwd.display()
+            )
+        })
+    }
+
+    /// Load and merge every configuration layer: defaults, user, repo, includes, then host
+    ///
+    /// With `lenient`, a merge failure falls back to defaults so the layers
+    /// can still be inspected and validated.
+    fn load_config(
+        root: &Path,
+        host: &str,
+        lenient: bool,
+    ) -> Result<(DotsConfig, Vec<ConfigLayer>)> {
+        let mut layers = Self::find_config_layers(root, host)?;
+
+        for layer in &layers {
+            if let (LayerKind::User | LayerKind::Include | LayerKind::Host, Some(path)) =
+                (layer.kind, &layer.path)
+            {
+                eprintln!("Loaded config from: {}", path.display());
+            }
+        }
+        if layers.len() == 1 {
+            eprintln!("No config file found, using defaults");
+        }
+
+        //> Older files are upgraded in memory, but say so
+        for path in layers.iter().filter_map(|layer| layer.path.as_ref()) {
+            let stale = Self::read_config_file(path)
+                .map(|raw| !Self::plan_config_migration(&raw).is_empty())
+                .unwrap_or(false);
+            if stale {
+                eprintln!(
+                    "Config at {} uses an older schema, run `dots config migrate`",
+                    path.display()
+                );
+            }
+        }
+
+        //> Host profiles override the sections they name for the selected host
+        if let Ok(merged) = Self::merge_config_values(&layers) {
+            let hostnames: HashMap<String, String> =
+                serde_json::from_value(merged["hostnames"].clone()).unwrap_or_default();
+            let host = Self::map_hostname(&hostnames, host);
+            layers.extend(Self::host_profile_layer(&merged, &host));
+        }
+
+        let config = match Self::merge_config_layers(&layers) {
+            Ok(config) => config,
+            Err(e) if lenient => {
+                eprintln!("Invalid config, using defaults: {:#}", e);
+                Self::default_config()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok((config, layers))
+    }
+
+    /// Collect the configuration layers that apply to this root and host
+    fn find_config_layers(root: &Path, host: &str) -> Result<Vec<ConfigLayer>> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let user_paths = vec![home.clone(), home.join(".config")];
+        let repo_paths = vec![root.to_path_buf()];
+
+        let filenames: Vec<String> = CONFIG_FILENAMES.iter().map(|f| f.to_string()).collect();
+        let host_filenames: Vec<String> = [
+            ".dots.{}.toml",
+            "dots.{}.toml",
+            ".dots.{}.json",
+            "dots.{}.json",
+        ]
+        .iter()
+        .map(|pattern| pattern.replace("{}", host))
+        .collect();
+
+        let mut layers = vec![ConfigLayer {
+            kind: LayerKind::Default,
+            path: None,
+            value: serde_json::to_value(Self::default_config())?,
+        }];
+
+        let user_file = Self::find_config_file(&user_paths, &filenames);
+        Self::push_config_layer(&mut layers, LayerKind::User, user_file)?;
+        let repo_file = Self::find_config_file(&repo_paths, &filenames);
+        Self::push_config_layer(&mut layers, LayerKind::Repo, repo_file)?;
+
+        //> Enabled includes may carry config fragments of their own
+        if let Ok(config) = Self::merge_config_layers(&layers) {
+            for include in config.includes.iter().filter(|include| include.enabled) {
+                let include_paths = vec![Self::resolve_include_path(root, &include.path)];
+                let include_file = Self::find_config_file(&include_paths, &filenames);
+                Self::push_config_layer(&mut layers, LayerKind::Include, include_file)?;
+            }
+        }
+
+        let user_host_file = Self::find_config_file(&user_paths, &host_filenames);
+        Self::push_config_layer(&mut layers, LayerKind::Host, user_host_file)?;
+        let repo_host_file = Self::find_config_file(&repo_paths, &host_filenames);
+        Self::push_config_layer(&mut layers, LayerKind::Host, repo_host_file)?;
+
+        Ok(layers)
+    }
+
+    /// Helper: Parse and append a layer, skipping missing or already loaded files
+    fn push_config_layer(
+        layers: &mut Vec<ConfigLayer>,
+        kind: LayerKind,
+        path: Option<PathBuf>,
+    ) -> Result<()> {
+        let Some(path) = path else {
+            return Ok(());
+        };
+
+        //> The repo may live in $HOME, so skip files already loaded
+        if layers
+            .iter()
+            .any(|layer| layer.path.as_ref() == Some(&path))
+        {
+            return Ok(());
+        }
+
+        layers.push(ConfigLayer {
+            kind,
+            value: Self::parse_config_file(&path)?,
+            path: Some(path),
+        });
+        Ok(())
+    }
+
+    /// Helper: Resolve an include path relative to the root, expanding `~`
+    fn resolve_include_path(root: &Path, path: &str) -> PathBuf {
+        let expanded = match path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+            None => PathBuf::from(path),
+        };
+        let resolved = root.join(expanded);
+        fs::canonicalize(&resolved).unwrap_or(resolved)
+    }
+
+    /// Helper: First existing config file, searching each directory in order
+    fn find_config_file(search_paths: &[PathBuf], filenames: &[String]) -> Option<PathBuf> {
+        search_paths
+            .iter()
+            .flat_map(|dir| filenames.iter().map(move |filename| dir.join(filename)))
+            .find(|path| path.is_file())
+            .map(|path| fs::canonicalize(&path).unwrap_or(path))
+    }
+
+    /// Parse a single configuration file, upgrading older schemas in memory
+    fn parse_config_file(config_path: &Path) -> Result<serde_json::Value> {
+        let mut value = Self::read_config_file(config_path)?;
+        for op in Self::plan_config_migration(&value) {
+            Self::apply_migration_op(&mut value, &op);
+        }
+        Ok(value)
+    }
+
+    /// Read a single configuration file as written, without migrations
+    fn read_config_file(config_path: &Path) -> Result<serde_json::Value> {
+        let config_str = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read config at {}", config_path.display()))?;
+
+        match ConfigFormat::from_path(config_path) {
+            ConfigFormat::Toml => {
+                let value: toml::Value = toml::from_str(&config_str).with_context(|| {
+                    format!("Failed to parse TOML config at {}", config_path.display())
+                })?;
+                Ok(serde_json::to_value(value)?)
+            }
+            ConfigFormat::Json => serde_json::from_str(&config_str).with_context(|| {
+                format!("Failed to parse JSON config at {}", config_path.display())
+            }),
+            ConfigFormat::Yaml => serde_yaml::from_str(&config_str).with_context(|| {
+                format!("Failed to parse YAML config at {}", config_path.display())
+            }),
+            ConfigFormat::Rc => Self::parse_rc_config(&config_str)
+                .map(|(value, _)| value)
+                .with_context(|| format!("Failed to parse rc config at {}", config_path.display())),
+        }
+    }
+
+    /// Parse `key = value` lines into a config value, with the line of each key
+    ///
+    /// Supports `#`/`;` comments, `[section]` headers, dotted keys, an optional
+    /// `export` prefix and quoted values. Legacy `DOTS_*` keys from the shell
+    /// version are mapped like environment overrides.
+    fn parse_rc_config(content: &str) -> Result<(serde_json::Value, HashMap<String, usize>)> {
+        let mut root = serde_json::json!({});
+        let mut lines = HashMap::new();
+        let mut section = String::new();
+
+        //> rc files are untyped, so known fields take their type from the defaults
+        let defaults = serde_json::to_value(Self::default_config()).unwrap_or_default();
+        let mut fields = Vec::new();
+        Self::flatten_config(&defaults, "", &mut fields);
+        let mut unset = Vec::new();
+        Self::null_config_fields(&defaults, "", &mut unset);
+        fields.extend(unset.into_iter().map(|key| (key, serde_json::Value::Null)));
+        let templates: HashMap<String, serde_json::Value> = fields.into_iter().collect();
+
+        for (index, raw_line) in content.lines().enumerate() {
+            let line_no = index + 1;
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
 
-        let search_paths = vec![
-            root.to_path_buf(),
-            dirs::home_dir().unwrap_or_default(),
-            dirs::home_dir().unwrap_or_default().join(".config"),
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(name) = header.strip_suffix(']') else {
+                    anyhow::bail!("line {}: unterminated section header: {}", line_no, line);
+                };
+                section = name.trim().to_string();
+                lines.entry(section.clone()).or_insert(line_no);
+                continue;
+            }
+
+            let assignment = line.strip_prefix("export ").unwrap_or(line);
+            let Some((raw_key, raw_value)) = assignment.split_once('=') else {
+                anyhow::bail!("line {}: expected `key = value`, found: {}", line_no, line);
+            };
+
+            let raw_key = raw_key.trim();
+            if raw_key.is_empty()
+                || !raw_key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                anyhow::bail!("line {}: invalid key: {:?}", line_no, raw_key);
+            }
+
+            let key = if raw_key.starts_with("DOTS_") && section.is_empty() {
+                templates
+                    .keys()
+                    .find(|key| Self::env_var_name(key) == raw_key)
+                    .cloned()
+                    .with_context(|| format!("line {}: unknown setting {}", line_no, raw_key))?
+            } else if section.is_empty() {
+                raw_key.to_string()
+            } else {
+                format!("{}.{}", section, raw_key)
+            };
+
+            let value = Self::parse_rc_value(raw_value)
+                .with_context(|| format!("line {}: invalid value for {}", line_no, key))?;
+            let value = match (templates.get(&key), value) {
+                (Some(serde_json::Value::Bool(_)), serde_json::Value::Number(n)) => {
+                    serde_json::Value::Bool(n.as_i64() != Some(0))
+                }
+                (
+                    Some(serde_json::Value::String(_)),
+                    value @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_)),
+                ) => serde_json::Value::String(value.to_string()),
+                (_, value) => value,
+            };
+
+            let mut target = &mut root;
+            for segment in key.split('.') {
+                target = target
+                    .as_object_mut()
+                    .with_context(|| format!("line {}: {} is not a table", line_no, key))?
+                    .entry(segment)
+                    .or_insert_with(|| serde_json::json!({}));
+            }
+            *target = value;
+            lines.insert(key, line_no);
+        }
+
+        Ok((root, lines))
+    }
+
+    /// Helper: Infer the type of an rc value (quoted string, bool, number or list)
+    fn parse_rc_value(raw: &str) -> Result<serde_json::Value> {
+        use serde_json::Value;
+
+        let raw = raw.trim();
+
+        for quote in ['"', '\''] {
+            if let Some(inner) = raw.strip_prefix(quote) {
+                let Some(end) = inner.find(quote) else {
+                    anyhow::bail!("unterminated string: {}", raw);
+                };
+                return Ok(Value::String(inner[..end].to_string()));
+            }
+        }
+
+        //> Unquoted values may carry a trailing comment
+        let value = match raw.find(" #") {
+            Some(index) => raw[..index].trim_end(),
+            None => raw,
+        };
+
+        if let Some(items) = value.strip_prefix('[') {
+            let Some(items) = items.strip_suffix(']') else {
+                anyhow::bail!("unterminated list: {}", value);
+            };
+            return items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(Self::parse_rc_value)
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array);
+        }
+
+        Ok(match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" => Value::Bool(true),
+            "false" | "no" | "off" => Value::Bool(false),
+            _ => value
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| value.parse::<f64>().map(Value::from))
+                .unwrap_or_else(|_| Value::String(value.to_string())),
+        })
+    }
+
+    /// Deep-merge layers in order, later layers overriding earlier ones
+    fn merge_config_layers(layers: &[ConfigLayer]) -> Result<DotsConfig> {
+        let merged = Self::merge_config_values(layers)?;
+
+        let sources = || {
+            let sources: Vec<String> = layers
+                .iter()
+                .filter_map(|layer| layer.path.as_ref())
+                .map(|path| path.display().to_string())
+                .collect();
+            format!("Failed to resolve config from: {}", sources.join(", "))
+        };
+        let config: DotsConfig = serde_json::from_value(merged).with_context(sources)?;
+
+        //> User fields share one attrset with the built-in ones in `dots info`
+        let mut reserved: Vec<&String> = config
+            .info
+            .fields
+            .keys()
+            .filter(|name| INFO_BUILTIN_FIELDS.contains(&name.as_str()))
+            .collect();
+        if !reserved.is_empty() {
+            reserved.sort();
+            return Err(anyhow::anyhow!(
+                "info.fields can't redefine built-in field(s): {}",
+                reserved
+                    .iter()
+                    .map(|name| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .with_context(sources);
+        }
+
+        Ok(config)
+    }
+
+    /// Helper: Deep-merge layers into a raw value without deserializing
+    fn merge_config_values(layers: &[ConfigLayer]) -> Result<serde_json::Value> {
+        //> Resolve the merge strategy first so every layer is merged the same way
+        let mut merge_value = serde_json::json!({});
+        for layer in layers {
+            if let Some(merge) = layer.value.get("merge") {
+                Self::deep_merge(&mut merge_value, merge, "", &MergeOptions::default());
+            }
+        }
+        let merge: MergeOptions =
+            serde_json::from_value(merge_value).context("Invalid [merge] configuration")?;
+
+        let mut merged = serde_json::json!({});
+        for layer in layers {
+            Self::deep_merge(&mut merged, &layer.value, "", &merge);
+        }
+        Ok(merged)
+    }
+
+    /// Build a layer from the `[hosts.<name>]` table matching `host`, if any
+    ///
+    /// Only the overridable sections are taken; lookup falls back to a
+    /// case-insensitive match so `[hosts.qbx]` applies to `QBX`.
+    fn host_profile_layer(merged: &serde_json::Value, host: &str) -> Option<ConfigLayer> {
+        let hosts = merged.get("hosts")?.as_object()?;
+        let profile = hosts.get(host).or_else(|| {
+            hosts
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(host))
+                .map(|(_, profile)| profile)
+        })?;
+
+        let overrides: serde_json::Map<String, serde_json::Value> = HOST_OVERRIDABLE
+            .iter()
+            .filter_map(|key| Some((key.to_string(), profile.get(*key)?.clone())))
+            .collect();
+
+        (!overrides.is_empty()).then_some(ConfigLayer {
+            kind: LayerKind::Profile,
+            path: None,
+            value: serde_json::Value::Object(overrides),
+        })
+    }
+
+    /// Schema version a config file was written for; unversioned files predate versioning
+    fn config_file_version(value: &serde_json::Value) -> u64 {
+        value
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .unwrap_or(1)
+    }
+
+    /// Edits that bring a config file up to `CONFIG_VERSION`, empty when current
+    fn plan_config_migration(value: &serde_json::Value) -> Vec<MigrationOp> {
+        let from = Self::config_file_version(value);
+
+        //> Plan each step against the result of the previous one
+        let mut working = value.clone();
+        let mut ops = Vec::new();
+        for (target, _, planner) in &CONFIG_MIGRATIONS {
+            if u64::from(*target) <= from {
+                continue;
+            }
+            for op in planner(&working) {
+                Self::apply_migration_op(&mut working, &op);
+                ops.push(op);
+            }
+        }
+
+        //> A file that names an old version (e.g. "0.8") is stale even if nothing moved
+        let outdated = value.get("version").is_some_and(|version| {
+            version
+                .as_u64()
+                .is_none_or(|version| version < u64::from(CONFIG_VERSION))
+        });
+        if !ops.is_empty() || outdated {
+            ops.push(MigrationOp::Set(
+                "version".to_string(),
+                CONFIG_VERSION.into(),
+            ));
+        }
+        ops
+    }
+
+    /// Migration to version 2: `[logging]` preferences and `experimental_features.enabled`
+    fn migrate_to_v2(value: &serde_json::Value) -> Vec<MigrationOp> {
+        let preferences = [
+            ("verbosePreference", "verbose"),
+            ("debugPreference", "debug"),
+            ("informationPreference", "information"),
+            ("warningPreference", "warning"),
+            ("errorActionPreference", "error_action"),
         ];
+        let features = [("nix_command", "nix-command"), ("flakes", "flakes")];
 
-        for search_path in &search_paths {
-            for filename in &config_filenames {
-                let config_path = search_path.join(filename);
-                if config_path.exists() {
-                    let config_str = fs::read_to_string(&config_path).with_context(|| {
-                        format!("Failed to read config at {}", config_path.display())
-                    })?;
-
-                    let config: DotsConfig = if filename.ends_with(".toml") {
-                        toml::from_str(&config_str).with_context(|| {
-                            format!("Failed to parse TOML config at {}", config_path.display())
-                        })?
-                    } else {
-                        serde_json::from_str(&config_str).with_context(|| {
-                            format!("Failed to parse JSON config at {}", config_path.display())
-                        })?
-                    };
+        //> Host profiles carry the same sections as the top level
+        let mut prefixes = vec![String::new()];
+        if let Some(hosts) = value.get("hosts").and_then(|hosts| hosts.as_object()) {
+            prefixes.extend(hosts.keys().map(|host| format!("hosts.{}.", host)));
+        }
 
-                    if search_path != root {
-                        eprintln!("Loaded config from: {}", config_path.display());
-                    }
-                    return Ok(config);
+        let mut ops = Vec::new();
+        for prefix in &prefixes {
+            for (old, new) in preferences {
+                let key = format!("{}options.{}", prefix, old);
+                if Self::config_lookup(value, &key).is_some() {
+                    ops.push(MigrationOp::Move(key, format!("{}logging.{}", prefix, new)));
+                }
+            }
+
+            let enabled_key = format!("{}experimental_features.enabled", prefix);
+            let original: Vec<serde_json::Value> = Self::config_lookup(value, &enabled_key)
+                .and_then(|enabled| enabled.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let mut enabled = original.clone();
+            for (flag, feature) in features {
+                let key = format!("{}experimental_features.{}", prefix, flag);
+                let Some(set) = Self::config_lookup(value, &key) else {
+                    continue;
+                };
+                if set.as_bool() == Some(true) && !enabled.contains(&feature.into()) {
+                    enabled.push(feature.into());
+                }
+                ops.push(MigrationOp::Remove(key));
+            }
+            if enabled != original {
+                ops.push(MigrationOp::Set(enabled_key, enabled.into()));
+            }
+        }
+        ops
+    }
+
+    /// Helper: Apply a migration edit to a config value
+    fn apply_migration_op(value: &mut serde_json::Value, op: &MigrationOp) {
+        match op {
+            MigrationOp::Move(from, to) => {
+                if let Some(moved) = Self::take_config_key(value, from) {
+                    Self::insert_config_key(value, to, moved);
                 }
             }
+            MigrationOp::Set(key, new) => Self::insert_config_key(value, key, new.clone()),
+            MigrationOp::Remove(key) => {
+                Self::take_config_key(value, key);
+            }
         }
+    }
 
-        eprintln!("No config file found, using defaults");
-        Ok(Self::default_config())
+    /// Helper: Remove a dotted key from a config value, returning what was there
+    fn take_config_key(value: &mut serde_json::Value, key: &str) -> Option<serde_json::Value> {
+        let (parents, leaf) = key.rsplit_once('.').unwrap_or(("", key));
+        let parent = parents
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .try_fold(value, |current, segment| current.get_mut(segment))?;
+        parent.as_object_mut()?.remove(leaf)
     }
 
-    fn default_config() -> DotsConfig {
-        let mut submodules = HashMap::new();
-        submodules.insert(
-            String::from("wallpapers"),
-            SubmoduleConfig {
-                path: String::from("Assets/Images/wallpaper"),
-                writable: true,
-                description: String::from("Wallpaper collection"),
-                user: String::from("Craole"),
-                auto_sync: true,
-                branch: String::from("main"),
+    /// Helper: Insert a dotted key into a config value, creating tables on the way
+    fn insert_config_key(value: &mut serde_json::Value, key: &str, new: serde_json::Value) {
+        let segments: Vec<&str> = key.split('.').collect();
+        let Some((leaf, parents)) = segments.split_last() else {
+            return;
+        };
+
+        let mut current = value;
+        for segment in parents {
+            let Some(map) = current.as_object_mut() else {
+                return;
+            };
+            current = map
+                .entry(segment.to_string())
+                .or_insert_with(|| serde_json::json!({}));
+        }
+        if let Some(map) = current.as_object_mut() {
+            map.insert(leaf.to_string(), new);
+        }
+    }
+
+    /// Build a layer from `DOTS_*` variables that name a known config field
+    ///
+    /// Names are derived from dotted keys, so `options.auto_confirm` is read from
+    /// `DOTS_OPTIONS_AUTO_CONFIRM` and `logging.error_action` from
+    /// `DOTS_LOGGING_ERROR_ACTION`. Values are parsed to the field's type.
+    fn env_config_layer(
+        loaded: &serde_json::Value,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Option<ConfigLayer>> {
+        let mut fields = Vec::new();
+        Self::flatten_config(loaded, "", &mut fields);
+        //> Unset optional fields can be overridden too, and take the value as a string
+        let mut unset = Vec::new();
+        Self::null_config_fields(loaded, "", &mut unset);
+        fields.extend(unset.into_iter().map(|key| (key, serde_json::Value::Null)));
+
+        let known: HashMap<String, (String, serde_json::Value)> = fields
+            .into_iter()
+            .map(|(key, template)| (Self::env_var_name(&key), (key, template)))
+            .collect();
+
+        let mut overrides = serde_json::json!({});
+        let mut found = false;
+
+        for (name, raw) in vars {
+            let Some((key, template)) = known.get(&name) else {
+                continue;
+            };
+            let value = Self::parse_env_value(template, &raw)
+                .with_context(|| format!("Invalid value for {}: {:?}", name, raw))?;
+
+            let mut target = &mut overrides;
+            for segment in key.split('.') {
+                target = target
+                    .as_object_mut()
+                    .context("Conflicting environment overrides")?
+                    .entry(segment)
+                    .or_insert_with(|| serde_json::json!({}));
+            }
+            *target = value;
+            found = true;
+        }
+
+        Ok(found.then_some(ConfigLayer {
+            kind: LayerKind::Environment,
+            path: None,
+            value: overrides,
+        }))
+    }
+
+    /// Helper: Environment variable name for a dotted config key
+    fn env_var_name(key: &str) -> String {
+        let mut name = String::from("DOTS");
+        for segment in key.split('.') {
+            name.push('_');
+            for (i, ch) in segment.chars().enumerate() {
+                //> camelCase keys (the PowerShell preferences) become SNAKE_CASE
+                if ch.is_uppercase() && i > 0 {
+                    name.push('_');
+                }
+                if ch == '-' {
+                    name.push('_');
+                } else {
+                    name.push(ch.to_ascii_uppercase());
+                }
+            }
+        }
+        name
+    }
+
+    /// Helper: Parse an environment value using an existing value as the type template
+    fn parse_env_value(template: &serde_json::Value, raw: &str) -> Result<serde_json::Value> {
+        use serde_json::Value;
+
+        let trimmed = raw.trim();
+        Ok(match template {
+            Value::Bool(_) => match trimmed.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Value::Bool(true),
+                "0" | "false" | "no" | "off" | "" => Value::Bool(false),
+                _ => anyhow::bail!("expected a boolean"),
             },
-        );
+            Value::Number(_) => trimmed
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| trimmed.parse::<f64>().map(Value::from))
+                .map_err(|_| anyhow::anyhow!("expected a number"))?,
+            Value::Array(_) if trimmed.starts_with('[') => serde_json::from_str(trimmed)?,
+            Value::Array(_) => Value::Array(
+                trimmed
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(Value::from)
+                    .collect(),
+            ),
+            Value::Object(_) => serde_json::from_str(trimmed)?,
+            Value::String(_) | Value::Null => Value::String(raw.to_string()),
+        })
+    }
+
+    /// Merge `overlay` into `base`: maps field by field, vectors per strategy
+    fn deep_merge(
+        base: &mut serde_json::Value,
+        overlay: &serde_json::Value,
+        path: &str,
+        merge: &MergeOptions,
+    ) {
+        use serde_json::Value;
+
+        match (base, overlay) {
+            (Value::Object(base_map), Value::Object(overlay_map)) => {
+                for (key, value) in overlay_map {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    match base_map.get_mut(key) {
+                        Some(existing) => Self::deep_merge(existing, value, &child_path, merge),
+                        None => {
+                            base_map.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+            (Value::Array(base_items), Value::Array(overlay_items))
+                if merge.strategy_for(path) == VectorMerge::Append =>
+            {
+                for item in overlay_items {
+                    if !base_items.contains(item) {
+                        base_items.push(item.clone());
+                    }
+                }
+            }
+            (base, overlay) => *base = overlay.clone(),
+        }
+    }
 
+    fn default_config() -> DotsConfig {
         DotsConfig {
             name: default_name(),
+            version: CONFIG_VERSION,
             git: GitConfig {
                 user: default_git_user(),
                 email: default_git_email(),
-                submodules,
+                submodules: HashMap::new(),
                 auto_push: true,
                 signing_key: None,
             },
             options: Options {
                 tag: default_tag(),
                 verbosity: default_verbosity(),
-                verbose_preference: "Continue".to_string(),
-                debug_preference: "Continue".to_string(),
-                information_preference: "Continue".to_string(),
-                warning_preference: "Continue".to_string(),
-                error_action_preference: "Continue".to_string(),
                 auto_copy: true,
                 auto_confirm: false,
                 color: true,
                 progress: true,
+                jobs: 0,
+                dirty_tree: "warn".to_string(),
+                notify: NotifyConfig::default(),
+            },
+            //> Continue would switch verbose and debug output on for everyone; these
+            //> were ignored before preferences were honored, so stay quiet by default
+            logging: Logging {
+                verbose: "SilentlyContinue".to_string(),
+                debug: "SilentlyContinue".to_string(),
+                information: "Continue".to_string(),
+                warning: "Continue".to_string(),
+                //> Continue carries on past failed commands, so only opt into it explicitly
+                error_action: "Stop".to_string(),
             },
             experimental_features: ExperimentalFeatures {
                 enabled: vec!["nix-command".to_string(), "flakes".to_string()],
-                nix_command: true,
-                flakes: true,
+            },
+            info: InfoConfig {
+                fields: [
+                    ("shell", "users.defaultUserShell"),
+                    (
+                        "displayManager",
+                        r#"builtins.filter (dm: (config.services.displayManager.${dm}.enable or false) || (config.services.xserver.displayManager.${dm}.enable or false)) [ "sddm" "gdm" "lightdm" "ly" "cosmic-greeter" ]"#,
+                    ),
+                    ("gpuDriver", "services.xserver.videoDrivers"),
+                    (
+                        "users",
+                        r#"builtins.filter (name: config.users.users.${name}.isNormalUser or false) (builtins.attrNames config.users.users)"#,
+                    ),
+                ]
+                .iter()
+                .map(|(name, field)| (name.to_string(), field.to_string()))
+                .collect(),
+                desktops: [
+                    ("plasma", "services.desktopManager.plasma6.enable"),
+                    ("gnome", "services.xserver.desktopManager.gnome.enable"),
+                    ("cosmic", "services.desktopManager.cosmic.enable"),
+                ]
+                .iter()
+                .map(|(name, option)| (name.to_string(), option.to_string()))
+                .collect(),
             },
             excludes: Excludes {
                 patterns: vec![
@@ -794,6 +2431,9 @@ impl DotDots {
             includes: vec![],
             hooks: Hooks::default(),
             aliases: HashMap::new(),
+            merge: MergeOptions::default(),
+            hostnames: HashMap::new(),
+            hosts: HashMap::new(),
         }
     }
 
@@ -826,7 +2466,13 @@ impl DotDots {
         let choice = input.trim();
 
         match choice {
-            "1" => self.handle_rebuild(None, true, false)?,
+            "1" => self.handle_rebuild(
+                None,
+                true,
+                false,
+                &DeployArgs::default(),
+                &DirtyArgs::default(),
+            )?,
             "2" => self.handle_update(true, None)?,
             "3" => self.handle_status(false, false, false)?,
             "4" => self.handle_fmt(false)?,
@@ -850,7 +2496,111 @@ impl DotDots {
         })
     }
 
-    /// Get current system from environment
+    /// Helper: Flake host for a machine hostname, via the `hostnames` map
+    fn map_hostname(hostnames: &HashMap<String, String>, hostname: &str) -> String {
+        hostnames
+            .iter()
+            .find(|(machine, _)| machine.eq_ignore_ascii_case(hostname))
+            .map(|(_, host)| host.clone())
+            .unwrap_or_else(|| hostname.to_string())
+    }
+
+    /// Flake host for this machine
+    fn current_flake_host(&self) -> String {
+        Self::map_hostname(&self.config.hostnames, &Self::get_current_host())
+    }
+
+    /// Resolve and validate the host to build against `nixosConfigurations`
+    ///
+    /// Case differences are corrected; unknown hosts fail with close matches.
+    /// If the flake can't be evaluated, nothing is built from an unchecked name.
+    fn resolve_host(&self, host: Option<&str>) -> Result<String> {
+        let requested = match host {
+            Some(host) => Self::map_hostname(&self.config.hostnames, host),
+            None => self.current_flake_host(),
+        };
+
+        //> A host added since the cached list was written gets a fresh lookup
+        let lookup = |names: &[String]| {
+            names
+                .iter()
+                .find(|name| **name == requested)
+                .or_else(|| {
+                    names
+                        .iter()
+                        .find(|name| name.eq_ignore_ascii_case(&requested))
+                })
+                .cloned()
+        };
+        let names = self.host_names(true).with_context(|| {
+            format!(
+                "Could not validate host {} against nixosConfigurations",
+                requested
+            )
+        })?;
+        let (found, names) = match lookup(&names) {
+            Some(found) => (Some(found), names),
+            None => {
+                let names = self.host_names(false)?;
+                (lookup(&names), names)
+            }
+        };
+
+        if let Some(found) = found {
+            if found != requested {
+                self.log_info(&format!("Using host {} for {}", found, requested), None);
+            }
+            return Ok(found);
+        }
+
+        let mut message = format!("Unknown host: {}", requested);
+        let close = Self::close_matches(&requested, &names);
+        if !close.is_empty() {
+            message.push_str(&format!("\nDid you mean: {}?", close.join(", ")));
+        }
+        message.push_str(&format!("\nAvailable hosts: {}", names.join(", ")));
+        if host.is_none() {
+            message.push_str(&format!(
+                "\nMap this machine with: dots config set hostnames.{} <host>",
+                Self::get_current_host()
+            ));
+        }
+        anyhow::bail!(message)
+    }
+
+    /// Helper: Names within a few edits of `requested`, closest first
+    fn close_matches<'a>(requested: &str, names: &'a [String]) -> Vec<&'a str> {
+        let mut matches: Vec<(usize, &str)> = names
+            .iter()
+            .map(|name| {
+                let distance = Self::edit_distance(&name.to_lowercase(), &requested.to_lowercase());
+                (distance, name.as_str())
+            })
+            .filter(|(distance, name)| *distance <= (name.len() / 3).max(2))
+            .collect();
+        matches.sort();
+        matches.into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Helper: Levenshtein distance between two strings
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = (diagonal + usize::from(ca != *cb))
+                    .min(row[j] + 1)
+                    .min(above + 1);
+                diagonal = above;
+            }
+        }
+        row[b.len()]
+    }
+
+    /// Get current system from environment
     fn get_current_system() -> String {
         env::var("HOSTTYPE").unwrap_or_else(|_| "x86_64-linux".to_string())
     }
@@ -885,6 +2635,11 @@ impl DotDots {
             ".info".cyan(),
             "dots info [host]".dimmed()
         );
+        println!(
+            "  {} or {} - Compare two hosts",
+            ".diff-hosts".cyan(),
+            "dots diff-hosts <a> <b>".dimmed()
+        );
         println!(
             "  {} or {} - Show rebuild command",
             ".rebuild".cyan(),
@@ -900,6 +2655,11 @@ impl DotDots {
             ".boot".cyan(),
             "dots boot [host]".dimmed()
         );
+        println!(
+            "  {} or {}    - Show home-manager command",
+            ".home".cyan(),
+            "dots home [switch|build|news] [user@host]".dimmed()
+        );
         println!(
             "  {} or {}     - Show dry-build command",
             ".dry".cyan(),
@@ -955,6 +2715,16 @@ impl DotDots {
             ".cache".cyan(),
             "dots cache".dimmed()
         );
+        println!(
+            "  {} or {}  - Inspect and edit configuration",
+            ".config".cyan(),
+            "dots config".dimmed()
+        );
+        println!(
+            "  {} or {}    - Scaffold a .dots.toml",
+            ".init".cyan(),
+            "dots init".dimmed()
+        );
         println!(
             "  {} or {}    - List all commands",
             ".list".cyan(),
@@ -979,6 +2749,7 @@ impl DotDots {
         println!("  Commands automatically copy to clipboard when available");
         println!("  Use --verbose for detailed output");
         println!("  Use --quiet for minimal output");
+        println!("  Use --root <path> outside the repository when DOTS is unset");
         println!();
 
         println!("{}", "Quick usage:".bold().magenta());
@@ -998,63 +2769,270 @@ impl DotDots {
     }
 
     /// List all hosts
-    fn list_hosts(&self) -> Result<()> {
-        match self
-            .nix_eval("builtins.attrNames (builtins.getFlake (toString ./.)).nixosConfigurations")
-        {
-            Ok(json) => {
-                if let Some(hosts) = json.as_array() {
-                    println!("{}", "Available hosts:".bold().cyan());
-                    for host in hosts {
-                        if let Some(host_str) = host.as_str() {
-                            let current = Self::get_current_host();
-                            if host_str == current {
-                                println!("  • {} (current)", host_str.green().bold());
-                            } else {
-                                println!("  • {}", host_str.green());
-                            }
-                        }
-                    }
-                } else {
-                    println!("{}", "No hosts found".yellow());
-                }
-            }
+    fn list_hosts(&self, as_json: bool) -> Result<()> {
+        //> One evaluation for every host. Each field is wrapped in `tryEval`, so a
+        //> host that throws shows up as an error row instead of hiding the rest
+        let expr = format!(
+            r#"
+      let
+        flake = builtins.getFlake (toString ./.);
+      in builtins.mapAttrs (name: host:
+        let
+          config = host.config;
+          fields = builtins.mapAttrs (_: builtins.tryEval) {{
+            system = config.nixpkgs.hostPlatform.system;
+            stateVersion = config.system.stateVersion;
+            desktop = {};
+            outPath = config.system.build.toplevel.outPath;
+          }};
+        in builtins.mapAttrs (_: field: if field.success then field.value else null) fields // {{
+          failed = builtins.filter (name: !fields.${{name}}.success) (builtins.attrNames fields);
+        }}) flake.nixosConfigurations
+      "#,
+            self.nix_desktop_expr("config")
+        );
+
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_message("Evaluating hosts...");
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        let result = self.nix_eval(&expr);
+        spinner.finish_and_clear();
+
+        //> Errors `tryEval` can't catch (undefined variables, type errors) still stop the listing
+        let hosts = match result {
+            Ok(json) => json.as_object().cloned().unwrap_or_default(),
             Err(e) => {
-                self.log_error(&format!("Failed to list hosts: {}", e), None);
+                let message = format!("{:#}", e);
+                let message = ANSI_ESCAPE.replace_all(&message, "");
+                self.log_error(&format!("Failed to list hosts: {}", message), None)?;
                 println!("{}", "Are you in a Nix flake directory?".yellow());
+                return Ok(());
             }
+        };
+
+        let current = self.current_flake_host();
+        let current_system = fs::read_link("/run/current-system").ok();
+        let rows: Vec<serde_json::Value> = hosts
+            .iter()
+            .map(|(name, info)| {
+                let failed: Vec<&str> = info["failed"]
+                    .as_array()
+                    .map(|fields| fields.iter().filter_map(|field| field.as_str()).collect())
+                    .unwrap_or_default();
+                let error = (!failed.is_empty())
+                    .then(|| format!("could not evaluate {}", failed.join(", ")));
+                let out_path = info["outPath"].as_str();
+                let is_current = name == &current;
+
+                //> current: running now, cached: built and in the store,
+                //> outdated: running or built from an older config, unbuilt: never built here
+                let build = match out_path {
+                    None => "error",
+                    Some(path) if current_system.as_deref() == Some(Path::new(path)) => "current",
+                    Some(path) if Path::new(path).exists() => "cached",
+                    Some(_) if is_current && current_system.is_some() => "outdated",
+                    Some(_)
+                        if self
+                            .cache_dir
+                            .join("builds")
+                            .join(format!("{}.json", name))
+                            .exists() =>
+                    {
+                        "outdated"
+                    }
+                    Some(_) => "unbuilt",
+                };
+
+                serde_json::json!({
+                    "name": name,
+                    "current": is_current,
+                    "system": info["system"],
+                    "stateVersion": info["stateVersion"],
+                    "desktop": info["desktop"],
+                    "outPath": out_path,
+                    "build": build,
+                    "error": error,
+                })
+            })
+            .collect();
+
+        if as_json {
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+            return Ok(());
+        }
+
+        if rows.is_empty() {
+            println!("{}", "No hosts found".yellow());
+            return Ok(());
+        }
+
+        let text = |row: &serde_json::Value, key: &str| -> String {
+            row[key].as_str().unwrap_or("-").to_string()
+        };
+        let name_width = rows
+            .iter()
+            .map(|row| text(row, "name").len())
+            .max()
+            .unwrap_or(0)
+            + 2;
+        let system_width = rows
+            .iter()
+            .map(|row| text(row, "system").len())
+            .max()
+            .unwrap_or(0);
+        let desktop_width = rows
+            .iter()
+            .map(|row| text(row, "desktop").len())
+            .max()
+            .unwrap_or(0);
+
+        println!(
+            "{}",
+            format!(
+                "{:<name_width$}  {:<system_width$}  {:<6}  {:<desktop_width$}  {}",
+                "HOST",
+                "SYSTEM",
+                "STATE",
+                "DESKTOP",
+                "BUILD",
+                name_width = name_width,
+                system_width = system_width.max(6),
+                desktop_width = desktop_width.max(7)
+            )
+            .bold()
+            .cyan()
+        );
+        for row in &rows {
+            let name = if row["current"] == true {
+                format!("{} *", text(row, "name"))
+            } else {
+                text(row, "name")
+            };
+            let build = text(row, "build");
+            let build = match build.as_str() {
+                "current" | "cached" => build.green(),
+                "outdated" => build.yellow(),
+                "error" => build.red(),
+                _ => build.dimmed(),
+            };
+            println!(
+                "{:<name_width$}  {:<system_width$}  {:<6}  {:<desktop_width$}  {}",
+                if row["current"] == true {
+                    name.green().bold()
+                } else {
+                    name.green()
+                },
+                text(row, "system"),
+                text(row, "stateVersion"),
+                text(row, "desktop"),
+                build,
+                name_width = name_width,
+                system_width = system_width.max(6),
+                desktop_width = desktop_width.max(7)
+            );
+        }
+        println!("\n{}", "* current host".dimmed());
+
+        for row in rows.iter().filter(|row| row["error"].is_string()) {
+            println!(
+                "{} {}",
+                text(row, "name").red(),
+                text(row, "error").dimmed()
+            );
         }
+
         Ok(())
     }
 
+    /// Helper: Names of the hosts in `nixosConfigurations`, optionally from a short-lived cache
+    fn host_names(&self, cached: bool) -> Result<Vec<String>> {
+        let expr = "builtins.attrNames (builtins.getFlake (toString ./.)).nixosConfigurations";
+        let json = if cached {
+            self.nix_eval_cached(expr, "host-names", Duration::from_secs(300))?
+        } else {
+            self.nix_eval(expr)?
+        };
+        Ok(json
+            .as_array()
+            .map(|hosts| {
+                hosts
+                    .iter()
+                    .filter_map(|host| host.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
     /// Show host information
-    fn show_host_info(&self, host: Option<&str>, as_json: bool, detailed: bool) -> Result<()> {
+    fn show_host_info(
+        &self,
+        host: Option<&str>,
+        as_json: bool,
+        detailed: bool,
+        attrs: &[String],
+    ) -> Result<()> {
         let host_name = host
             .map(String::from)
-            .unwrap_or_else(Self::get_current_host);
-        let cache_key = format!("host-info-{}", host_name);
+            .unwrap_or_else(|| self.current_flake_host());
+
+        //> Configured fields first, so an ad-hoc --attr with the same name wins
+        let mut fields: BTreeMap<String, String> = self
+            .config
+            .info
+            .fields
+            .iter()
+            .map(|(name, field)| (name.clone(), field.clone()))
+            .collect();
+        if let Some(attr) = attrs
+            .iter()
+            .find(|attr| INFO_BUILTIN_FIELDS.contains(&attr.as_str()))
+        {
+            anyhow::bail!(
+                "'{}' is already shown by `dots info`, use (config.{}) to read the option",
+                attr,
+                attr
+            );
+        }
+        fields.extend(attrs.iter().map(|attr| (attr.clone(), attr.clone())));
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(name, field)| {
+                format!(
+                    "{} = sanitize 3 ({});",
+                    Self::nix_string(name),
+                    Self::nix_field_expr(field)
+                )
+            })
+            .collect();
 
         let expr = format!(
             r#"
       let
         flake = builtins.getFlake (toString ./.);
-        hostConfig = flake.nixosConfigurations."{}";
+        hostConfig = flake.nixosConfigurations.{};
+        config = hostConfig.config;
+        pkgs = hostConfig.pkgs;
+        sanitize = {};
       in {{
-        hostname = hostConfig.config.networking.hostName;
-        system = hostConfig.config.nixpkgs.hostPlatform.system;
-        kernel = hostConfig.config.boot.kernelPackages.kernel.version;
-        stateVersion = hostConfig.config.system.stateVersion;
-        desktop = (
-          if hostConfig.config.services.desktopManager.plasma6.enable or false then "plasma"
-          else if hostConfig.config.services.xserver.desktopManager.gnome.enable or false then "gnome"
-          else if hostConfig.config.services.desktopManager.cosmic.enable or false then "cosmic"
-          else "none"
-        );
+        hostname = config.networking.hostName;
+        system = config.nixpkgs.hostPlatform.system;
+        kernel = config.boot.kernelPackages.kernel.version;
+        stateVersion = config.system.stateVersion;
+        desktop = {};
+        {}
       }}
       "#,
-            host_name.replace('\'', "'\\''")
+            Self::nix_string(&host_name),
+            NIX_SANITIZE,
+            self.nix_desktop_expr("config"),
+            fields.join("\n        ")
         );
 
+        //> Fields come from config, so key the cache on the expression too
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        expr.hash(&mut hasher);
+        let cache_key = format!("host-info-{}-{:x}", host_name, hasher.finish());
+
         // Use cached version with 5 minute TTL - DON'T call nix_eval again!
         match self.nix_eval_cached(&expr, &cache_key, Duration::from_secs(300)) {
             Ok(info) => {
@@ -1065,8 +3043,19 @@ impl DotDots {
                     println!("{}", "─".repeat(40).dimmed());
 
                     if let Some(obj) = info.as_object() {
+                        let width = obj
+                            .keys()
+                            .map(|key| key.len() + 1)
+                            .max()
+                            .unwrap_or(0)
+                            .max(15);
                         for (key, value) in obj {
-                            println!("  {:<15} {}", format!("{}:", key).cyan(), value);
+                            println!(
+                                "  {:<width$} {}",
+                                format!("{}:", key).cyan(),
+                                value,
+                                width = width
+                            );
                         }
                     }
 
@@ -1088,10 +3077,306 @@ impl DotDots {
                 }
             }
             Err(_) => {
-                self.log_error(&format!("Host not found: {}", host_name), None);
+                self.log_error(&format!("Host not found: {}", host_name), None)?;
                 println!("\n{}", "Available hosts:".yellow());
-                self.list_hosts()?;
+                for name in self.host_names(false).unwrap_or_default() {
+                    println!("  • {}", name.green());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Helper: Nix expression for an info field
+    ///
+    /// A plain dotted path such as `services.xserver.videoDrivers` is read from
+    /// `config`; anything else is used as a Nix expression.
+    fn nix_field_expr(field: &str) -> String {
+        let is_path = field.split('.').all(|segment| {
+            segment
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''))
+        });
+
+        if is_path {
+            let path: Vec<String> = field.split('.').map(Self::nix_string).collect();
+            format!("config.{} or null", path.join("."))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Helper: Nix expression naming the enabled desktops from `info.desktops`
+    fn nix_desktop_expr(&self, config: &str) -> String {
+        let mut desktops: Vec<(&String, &String)> = self.config.info.desktops.iter().collect();
+        desktops.sort();
+        let checks: Vec<String> = desktops
+            .iter()
+            .map(|(name, option)| {
+                let path: Vec<String> = option.split('.').map(Self::nix_string).collect();
+                format!(
+                    "{} = {}.{} or false;",
+                    Self::nix_string(name),
+                    config,
+                    path.join(".")
+                )
+            })
+            .collect();
+
+        format!(
+            "(let desktops = {{ {} }}; enabled = builtins.filter (name: desktops.${{name}} == true) (builtins.attrNames desktops); in if enabled == [] then \"none\" else builtins.concatStringsSep \", \" enabled)",
+            checks.join(" ")
+        )
+    }
+
+    /// Compare packages, enabled services and selected options of two hosts
+    fn handle_diff_hosts(
+        &self,
+        host_a: &str,
+        host_b: &str,
+        attrs: &[String],
+        as_json: bool,
+    ) -> Result<()> {
+        let attrs: Vec<String> = if attrs.is_empty() {
+            [
+                "networking.hostName",
+                "system.stateVersion",
+                "time.timeZone",
+                "i18n.defaultLocale",
+                "nix.settings.experimental-features",
+                "hardware.graphics.enable",
+            ]
+            .iter()
+            .map(|attr| attr.to_string())
+            .collect()
+        } else {
+            attrs.to_vec()
+        };
+
+        //> Quote every segment so paths like nix.settings.experimental-features work
+        let selections: Vec<String> = attrs
+            .iter()
+            .map(|attr| {
+                let path: Vec<String> = attr.split('.').map(Self::nix_string).collect();
+                format!(
+                    "{} = sanitize 4 (config.{} or null);",
+                    Self::nix_string(attr),
+                    path.join(".")
+                )
+            })
+            .collect();
+
+        let expr = format!(
+            r#"
+      let
+        flake = builtins.getFlake (toString ./.);
+        sanitize = {};
+        describe = host:
+          let config = flake.nixosConfigurations.${{host}}.config; in {{
+            packages = builtins.listToAttrs (map (p: {{
+              name = p.pname or (builtins.parseDrvName (p.name or "unknown")).name;
+              value = p.version or "";
+            }}) config.environment.systemPackages);
+            services = builtins.filter (name: (builtins.tryEval (
+              let service = config.services.${{name}}; in
+              builtins.isAttrs service && (service.enable or false) == true
+            )).value) (builtins.attrNames config.services);
+            options = {{ {} }};
+          }};
+      in {{
+        a = describe {};
+        b = describe {};
+      }}
+      "#,
+            NIX_SANITIZE,
+            selections.join(" "),
+            Self::nix_string(host_a),
+            Self::nix_string(host_b)
+        );
+
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_message(format!("Evaluating {} and {}...", host_a, host_b));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        let result = self.nix_eval(&expr);
+        spinner.finish_and_clear();
+        let result =
+            result.with_context(|| format!("Failed to evaluate {} and {}", host_a, host_b))?;
+
+        let (a, b) = (&result["a"], &result["b"]);
+
+        //> Packages: name -> version on each side
+        let packages = |side: &serde_json::Value| -> BTreeMap<String, String> {
+            side["packages"]
+                .as_object()
+                .map(|packages| {
+                    packages
+                        .iter()
+                        .map(|(name, version)| {
+                            (
+                                name.clone(),
+                                version.as_str().unwrap_or_default().to_string(),
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let (packages_a, packages_b) = (packages(a), packages(b));
+        let added: Vec<(&String, &String)> = packages_b
+            .iter()
+            .filter(|(name, _)| !packages_a.contains_key(*name))
+            .collect();
+        let removed: Vec<(&String, &String)> = packages_a
+            .iter()
+            .filter(|(name, _)| !packages_b.contains_key(*name))
+            .collect();
+        let changed: Vec<(&String, &String, &String)> = packages_a
+            .iter()
+            .filter_map(|(name, version)| {
+                let other = packages_b.get(name)?;
+                (other != version).then_some((name, version, other))
+            })
+            .collect();
+
+        //> Services: names enabled on each side
+        let services = |side: &serde_json::Value| -> BTreeSet<String> {
+            side["services"]
+                .as_array()
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(|name| name.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let (services_a, services_b) = (services(a), services(b));
+        let services_added: Vec<&String> = services_b.difference(&services_a).collect();
+        let services_removed: Vec<&String> = services_a.difference(&services_b).collect();
+
+        //> Options: compare flattened leaves of each selected subtree
+        let mut options: Vec<(String, Option<serde_json::Value>, Option<serde_json::Value>)> =
+            Vec::new();
+        for attr in &attrs {
+            let (mut leaves_a, mut leaves_b) = (Vec::new(), Vec::new());
+            Self::flatten_config(&a["options"][attr], attr, &mut leaves_a);
+            Self::flatten_config(&b["options"][attr], attr, &mut leaves_b);
+            let leaves_a: BTreeMap<String, serde_json::Value> = leaves_a.into_iter().collect();
+            let leaves_b: BTreeMap<String, serde_json::Value> = leaves_b.into_iter().collect();
+
+            let keys: BTreeSet<&String> = leaves_a.keys().chain(leaves_b.keys()).collect();
+            for key in keys {
+                let (value_a, value_b) = (leaves_a.get(key), leaves_b.get(key));
+                if value_a != value_b {
+                    options.push((key.clone(), value_a.cloned(), value_b.cloned()));
+                }
+            }
+        }
+
+        if as_json {
+            let listed = |packages: &[(&String, &String)]| -> Vec<serde_json::Value> {
+                packages
+                    .iter()
+                    .map(|(name, version)| serde_json::json!({ "name": name, "version": version }))
+                    .collect()
+            };
+            let changed: Vec<serde_json::Value> = changed
+                .iter()
+                .map(|(name, from, to)| serde_json::json!({ "name": name, "from": from, "to": to }))
+                .collect();
+            let options: Vec<serde_json::Value> = options
+                .iter()
+                .map(|(key, from, to)| serde_json::json!({ "key": key, "from": from, "to": to }))
+                .collect();
+
+            let diff = serde_json::json!({
+                "from": host_a,
+                "to": host_b,
+                "packages": {
+                    "added": listed(&added),
+                    "removed": listed(&removed),
+                    "changed": changed,
+                },
+                "services": {
+                    "added": services_added,
+                    "removed": services_removed,
+                },
+                "options": options,
+            });
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+            return Ok(());
+        }
+
+        let with_version = |name: &str, version: &str| {
+            if version.is_empty() {
+                name.to_string()
+            } else {
+                format!("{} {}", name, version)
             }
+        };
+
+        println!(
+            "{} {} {} {}",
+            "Comparing".bold().cyan(),
+            host_a.yellow().bold(),
+            "→".dimmed(),
+            host_b.green().bold()
+        );
+        println!("{}", "─".repeat(40).dimmed());
+
+        println!(
+            "\n{} (+{} -{} ~{})",
+            "Packages".bold().cyan(),
+            added.len(),
+            removed.len(),
+            changed.len()
+        );
+        for (name, version) in &added {
+            println!("  {} {}", "+".green(), with_version(name, version));
+        }
+        for (name, version) in &removed {
+            println!("  {} {}", "-".red(), with_version(name, version));
+        }
+        for (name, from, to) in &changed {
+            println!("  {} {} {} → {}", "~".yellow(), name, from.dimmed(), to);
+        }
+
+        println!(
+            "\n{} (+{} -{})",
+            "Services".bold().cyan(),
+            services_added.len(),
+            services_removed.len()
+        );
+        for name in &services_added {
+            println!("  {} {}", "+".green(), name);
+        }
+        for name in &services_removed {
+            println!("  {} {}", "-".red(), name);
+        }
+
+        println!("\n{} ({} differ)", "Options".bold().cyan(), options.len());
+        let width = options
+            .iter()
+            .map(|(key, _, _)| key.len())
+            .max()
+            .unwrap_or(0);
+        let show = |value: &Option<serde_json::Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(unset)".to_string(),
+        };
+        for (key, from, to) in &options {
+            println!(
+                "  {:<width$}  {} → {}",
+                key.cyan(),
+                show(from).dimmed(),
+                show(to),
+                width = width
+            );
         }
 
         Ok(())
@@ -1110,7 +3395,7 @@ impl DotDots {
         }
 
         let mut process = Command::new("sh");
-        process.arg("-c").arg(cmd);
+        process.arg("-c").arg(cmd).env("DOTS", &self.root);
 
         if let Some(dir) = dir {
             process.current_dir(dir);
@@ -1142,14 +3427,20 @@ impl DotDots {
             sp.finish_and_clear();
         }
 
+        self.check_status(status, name)
+    }
+
+    /// Helper: Turn a command's exit status into a result
+    ///
+    /// A failed command is reported through `log_error`, so `logging.error_action`
+    /// decides whether the operation carries on, asks first, or stops.
+    fn check_status(&self, status: std::process::ExitStatus, name: &str) -> Result<()> {
         if status.success() {
-            Ok(())
-        } else {
-            anyhow::bail!(
-                "Command failed with exit code: {}",
-                status.code().unwrap_or(1)
-            );
+            return Ok(());
         }
+
+        let code = status.code().unwrap_or(1);
+        self.log_error(&format!("{} failed with exit code {}", name, code), None)
     }
 
     /// Execute and return output (for display)
@@ -1159,7 +3450,7 @@ impl DotDots {
         }
 
         let mut process = Command::new("sh");
-        process.arg("-c").arg(cmd);
+        process.arg("-c").arg(cmd).env("DOTS", &self.root);
         if let Some(dir) = dir {
             process.current_dir(dir);
         }
@@ -1181,53 +3472,115 @@ impl DotDots {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     }
 
-    /// Run multiple commands in parallel (useful for checks, formatting, etc.)
+    /// Run multiple commands in parallel (useful for checks, builds, etc.)
+    ///
+    /// At most `jobs` commands run at once; results keep the input order and
+    /// carry timing and captured output.
     fn execute_parallel(
         &self,
-        commands: Vec<(&str, &str)>, // (command, description)
-    ) -> Result<Vec<Result<(), String>>> {
+        commands: Vec<(String, String)>, // (command, description)
+        jobs: usize,
+    ) -> Vec<ParallelResult> {
+        use std::sync::{Arc, Mutex};
         use std::thread;
+        use std::time::Instant;
+
+        let total = commands.len();
+        let queue = Arc::new(Mutex::new(
+            commands.into_iter().enumerate().collect::<Vec<_>>(),
+        ));
+        let results = Arc::new(Mutex::new(Vec::with_capacity(total)));
+
+        let progress = if !self.quiet && self.config.options.progress {
+            let bar = ProgressBar::new(total as u64);
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{spinner:.cyan} [{pos}/{len}] {msg}")
+                    .unwrap(),
+            );
+            bar.enable_steady_tick(Duration::from_millis(100));
+            Some(bar)
+        } else {
+            None
+        };
 
-        let handles: Vec<_> = commands
-            .into_iter()
-            .map(|(cmd, desc)| {
-                let cmd = cmd.to_string();
-                let desc = desc.to_string();
+        let handles: Vec<_> = (0..jobs.clamp(1, total.max(1)))
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let results = Arc::clone(&results);
+                let progress = progress.clone();
+                let root = self.root.clone();
 
                 thread::spawn(move || {
-                    let output = Command::new("sh").arg("-c").arg(&cmd).output();
-
-                    match output {
-                        Ok(out) if out.status.success() => Ok(()),
-                        Ok(out) => Err(format!(
-                            "{}: {}",
-                            desc,
-                            String::from_utf8_lossy(&out.stderr)
-                        )),
-                        Err(e) => Err(format!("{}: {}", desc, e)),
+                    loop {
+                        //> Take from the front so commands start in the order given
+                        let next = {
+                            let mut queue = queue.lock().unwrap();
+                            (!queue.is_empty()).then(|| queue.remove(0))
+                        };
+                        let Some((index, (cmd, desc))) = next else {
+                            break;
+                        };
+
+                        let started = Instant::now();
+                        let output = Command::new("sh")
+                            .arg("-c")
+                            .arg(&cmd)
+                            .env("DOTS", &root)
+                            .current_dir(&root)
+                            .output();
+
+                        let result = match output {
+                            Ok(out) => ParallelResult {
+                                description: desc,
+                                success: out.status.success(),
+                                duration: started.elapsed(),
+                                stdout: String::from_utf8_lossy(&out.stdout).to_string(),
+                                stderr: String::from_utf8_lossy(&out.stderr).to_string(),
+                            },
+                            Err(e) => ParallelResult {
+                                description: desc,
+                                success: false,
+                                duration: started.elapsed(),
+                                stdout: String::new(),
+                                stderr: e.to_string(),
+                            },
+                        };
+
+                        if let Some(progress) = &progress {
+                            progress.inc(1);
+                            progress.set_message(format!("{} finished", result.description));
+                        }
+                        results.lock().unwrap().push((index, result));
                     }
                 })
             })
             .collect();
 
-        let results = handles
-            .into_iter()
-            .map(|h| {
-                h.join()
-                    .unwrap_or_else(|_| Err("Thread panicked".to_string()))
-            })
-            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+        if let Some(progress) = progress {
+            progress.finish_and_clear();
+        }
 
-        Ok(results)
+        let mut results = std::mem::take(&mut *results.lock().unwrap());
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
     }
 
     /// Run a list of hooks
+    ///
+    /// A failing hook follows `logging.error_action` (see `check_status`):
+    /// Continue reports it and runs the rest, SilentlyContinue skips it quietly,
+    /// Stop aborts, and Inquire asks whether to go on.
     fn run_hooks(&self, hooks: &[String]) -> Result<()> {
         for hook in hooks {
             if self.verbose {
                 self.log_debug(&format!("Running hook: {}", hook), None);
             }
-            self.execute(hook, "hook", None)?;
+
+            self.execute(hook, &format!("Hook '{}'", hook), None)?;
         }
         Ok(())
     }
@@ -1242,6 +3595,9 @@ impl DotDots {
     }
 
     /// Unified command execution flow
+    ///
+    /// Returns whether the command actually ran: `false` when it was only
+    /// printed (no `--execute`) or the user declined the confirmation.
     fn handle_command_flow(
         &self,
         cmd: &str,
@@ -1249,7 +3605,7 @@ impl DotDots {
         action_desc: &str,
         pre_hooks: &[String],
         post_hooks: &[String],
-    ) -> Result<()> {
+    ) -> Result<bool> {
         // Show command without executing
         if !execute {
             println!("{}", cmd.bright_white());
@@ -1258,7 +3614,7 @@ impl DotDots {
             }
             self.log_info("Add --execute to run immediately", None);
             // println!("{}", "Add --execute to run immediately".yellow());
-            return Ok(());
+            return Ok(false);
         }
 
         // 1. Execute pre-hooks
@@ -1270,13 +3626,40 @@ impl DotDots {
         // 3. Resolve any aliases in command
         let resolved_cmd = self.resolve_alias(cmd);
 
-        // 4. Execute with appropriate method based on command
-        if action_desc.contains("nix-collect-garbage")
-            || action_desc.contains("rollback")
-            || action_desc.contains("rebuild")
-        {
+        // 4. Show what a local NixOS build changes before it is applied
+        //> Only this machine's own config is comparable with /run/current-system, and
+        //> a --build-host build must not be done locally just to show the diff
+        let rebuild = Self::rebuild_target(&resolved_cmd);
+        if let Some((action, host)) = &rebuild {
+            if resolved_cmd.contains("--target-host") || resolved_cmd.contains("--build-host") {
+                self.log_debug("Skipping closure diff for a remote build", None);
+            } else if *host != self.current_flake_host() {
+                self.log_debug(
+                    &format!("Skipping closure diff, {} is not this machine", host),
+                    None,
+                );
+            } else {
+                self.show_closure_diff(host, action != "dry-build")?;
+            }
+        }
+
+        // 5. Execute with appropriate method based on command
+        let desc = action_desc.to_lowercase();
+        let destructive = resolved_cmd.contains("nix-collect-garbage")
+            || resolved_cmd.contains("--delete-generations")
+            || desc.contains("rolling back")
+            || desc.contains("rebuilding");
+        if let Some((action, host)) = &rebuild {
+            // NixOS builds, kept in a build log
+            if destructive && !self.confirm_destructive(&resolved_cmd)? {
+                return Ok(false);
+            }
+            self.execute_logged(&resolved_cmd, action_desc, action, host)?;
+        } else if destructive {
             // Destructive operations
-            self.execute_safe(&resolved_cmd, action_desc, true)?;
+            if !self.execute_safe(&resolved_cmd, action_desc, true)? {
+                return Ok(false);
+            }
         } else if self.config.options.progress
             && (action_desc.contains("fmt")
                 || action_desc.contains("check")
@@ -1286,87 +3669,365 @@ impl DotDots {
             self.execute_with_progress(&resolved_cmd, action_desc, Some(&self.root), true)?;
         } else {
             // Standard execution
-            self.execute(&resolved_cmd, action_desc, None)?;
+            self.execute(&resolved_cmd, action_desc, Some(&self.root))?;
         }
 
-        // 5. Execute post-hooks
+        // 6. Execute post-hooks
         self.run_hooks(post_hooks)?;
 
-        // 6. Log success
+        // 7. Log success
         self.log_success(&format!("{} completed!", action_desc), None);
-        Ok(())
+        Ok(true)
     }
 
-    fn handle_healthcheck(&self) -> Result<()> {
-        self.log_info("Running system health checks...", None);
+    /// Helper: Action and host of a `nixos-rebuild <action> --flake .#<host>` command
+    fn rebuild_target(cmd: &str) -> Option<(String, String)> {
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+        let at = words.iter().position(|word| *word == "nixos-rebuild")?;
+        let action = words.get(at + 1)?;
+        let host = words.iter().find_map(|word| word.strip_prefix(".#"))?;
+        Some((action.to_string(), host.to_string()))
+    }
 
-        let checks = vec![
-            ("Flake valid", "nix flake check --no-build"),
-            ("Git clean", "git status --porcelain"),
-            ("Disk space", "df -h / | tail -1"),
-            (
-                "Nix store",
-                "nix store optimise --dry-run 2>&1 | grep 'freed'",
-            ),
-        ];
+    /// Compare this machine's toplevel closure with `/run/current-system`
+    ///
+    /// With `build`, the toplevel is built first (nixos-rebuild reuses it);
+    /// otherwise the diff is only shown if it is already in the store. Sizes
+    /// and versions come from `nix path-info -rS --json`.
+    fn show_closure_diff(&self, host: &str, build: bool) -> Result<()> {
+        let current = Path::new("/run/current-system");
+        if !current.exists() {
+            return Ok(());
+        }
 
-        println!();
-        for (name, cmd) in checks {
-            print!("  {} ... ", name.cyan());
-            io::stdout().flush()?;
+        let installable = format!(
+            ".#nixosConfigurations.{}.config.system.build.toplevel",
+            Self::nix_string(host)
+        );
+        let mut args = vec![if build { "build" } else { "eval" }, installable.as_str()];
+        if build {
+            args.extend(["--no-link", "--print-out-paths"]);
+        } else {
+            args.extend(["--raw", "--apply", "drv: drv.outPath"]);
+        }
 
-            let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_message(format!("Building {} to compare closures...", host));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        let output = Command::new("nix")
+            .args(&args)
+            .env("NIX_CONFIG", "experimental-features = nix-command flakes")
+            .current_dir(&self.root)
+            .output()
+            .context("Failed to execute nix")?;
+        spinner.finish_and_clear();
 
-            if output.status.success() {
-                println!("{}", "✓".green());
-            } else {
-                println!("{}", "✗".red());
-                if self.verbose {
-                    println!(
-                        "    {}",
-                        String::from_utf8_lossy(&output.stderr).trim().dimmed()
-                    );
-                }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if build {
+                anyhow::bail!(
+                    "Failed to build {}:\n{}",
+                    host,
+                    self.summarize_nix_error(&stderr)
+                );
             }
+            self.log_debug(&format!("No closure diff: {}", stderr.trim()), None);
+            return Ok(());
         }
 
-        Ok(())
-    }
-
-    /// Handle rebuild command
-    fn handle_rebuild(&self, host: Option<&str>, execute: bool, command_only: bool) -> Result<()> {
-        let host_name = host
-            .map(String::from)
-            .unwrap_or_else(Self::get_current_host);
-        let cmd = format!("sudo nixos-rebuild switch --flake .#{}", host_name);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let new = PathBuf::from(stdout.lines().last().unwrap_or_default().trim());
+        if !new.exists() {
+            self.log_info(
+                "Not built yet, run `dots build` to see the closure diff",
+                None,
+            );
+            return Ok(());
+        }
+
+        if let Err(e) = self.print_closure_diff(current, &new) {
+            self.log_warn(&format!("Could not compare closures: {:#}", e), None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Helper: Print package version changes and the size delta between two closures
+    fn print_closure_diff(&self, old: &Path, new: &Path) -> Result<()> {
+        let old_closure = self.closure_info(old)?;
+        let new_closure = self.closure_info(new)?;
+
+        let versions = |closure: &ClosureInfo| -> BTreeMap<String, BTreeSet<String>> {
+            let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+            for path in &closure.paths {
+                let (name, version) = Self::split_store_name(path);
+                versions.entry(name).or_default().insert(version);
+            }
+            versions
+        };
+        let (old_versions, new_versions) = (versions(&old_closure), versions(&new_closure));
+        let join = |versions: &BTreeSet<String>| {
+            versions
+                .iter()
+                .filter(|version| !version.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        println!(
+            "\n{} {} {} {}",
+            "Closure diff:".bold().cyan(),
+            old.display(),
+            "→".dimmed(),
+            new.display()
+        );
+        let mut changes = 0;
+        for (name, versions) in &new_versions {
+            match old_versions.get(name) {
+                Some(old) if old != versions => {
+                    changes += 1;
+                    println!(
+                        "  {} {} {} → {}",
+                        "~".yellow(),
+                        name,
+                        join(old).dimmed(),
+                        join(versions)
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    changes += 1;
+                    println!("  {} {} {}", "+".green(), name, join(versions));
+                }
+            }
+        }
+        for (name, versions) in &old_versions {
+            if !new_versions.contains_key(name) {
+                changes += 1;
+                println!("  {} {} {}", "-".red(), name, join(versions).dimmed());
+            }
+        }
+        if changes == 0 {
+            println!("  {}", "No package changes".dimmed());
+        }
+
+        let delta = new_closure.size as i64 - old_closure.size as i64;
+        let delta = if delta >= 0 {
+            format!("+{}", Self::format_bytes(delta.unsigned_abs()))
+        } else {
+            format!("-{}", Self::format_bytes(delta.unsigned_abs()))
+        };
+        println!(
+            "  Closure size: {} → {} ({})\n",
+            Self::format_bytes(old_closure.size),
+            Self::format_bytes(new_closure.size),
+            delta.bold()
+        );
+
+        Ok(())
+    }
+
+    /// Helper: Store paths and total size of a closure, from `nix path-info -rS --json`
+    fn closure_info(&self, path: &Path) -> Result<ClosureInfo> {
+        let output = Command::new("nix")
+            .args(["path-info", "-rS", "--json"])
+            .arg(path)
+            .env("NIX_CONFIG", "experimental-features = nix-command flakes")
+            .output()
+            .context("Failed to execute nix path-info")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "nix path-info failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .context("Failed to parse nix path-info output")?;
+        let root = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Ok(Self::parse_path_info(json, &root))
+    }
+
+    /// Helper: Closure paths and size from `nix path-info -rS --json` output for `root`
+    fn parse_path_info(json: serde_json::Value, root: &Path) -> ClosureInfo {
+        //> Older nix prints a list of objects, newer nix an object keyed by path
+        let entries: Vec<(String, serde_json::Value)> = match json {
+            serde_json::Value::Array(items) => items
+                .into_iter()
+                .filter_map(|item| Some((item["path"].as_str()?.to_string(), item)))
+                .collect(),
+            serde_json::Value::Object(map) => map.into_iter().collect(),
+            _ => Vec::new(),
+        };
+
+        let size = entries
+            .iter()
+            .find(|(store_path, _)| Path::new(store_path) == root)
+            .and_then(|(_, info)| info["closureSize"].as_u64())
+            .unwrap_or_else(|| {
+                entries
+                    .iter()
+                    .filter_map(|(_, info)| info["narSize"].as_u64())
+                    .sum()
+            });
+        let paths = entries
+            .into_iter()
+            .map(|(store_path, _)| store_path)
+            .filter(|store_path| Path::new(store_path) != root)
+            .collect();
+
+        ClosureInfo { paths, size }
+    }
+
+    /// Helper: Split a store path into package name and version, like `builtins.parseDrvName`
+    fn split_store_name(path: &str) -> (String, String) {
+        let base = path.rsplit('/').next().unwrap_or(path);
+        //> Drop the 32 character hash and its dash
+        let name = base.get(33..).unwrap_or(base);
+        let version_start = name
+            .char_indices()
+            .find(|(i, c)| {
+                *c == '-'
+                    && name[i + 1..]
+                        .chars()
+                        .next()
+                        .is_some_and(|next| next.is_ascii_digit())
+            })
+            .map(|(i, _)| i);
+        match version_start {
+            Some(i) => (name[..i].to_string(), name[i + 1..].to_string()),
+            None => (name.to_string(), String::new()),
+        }
+    }
+
+    /// Helper: Human-readable byte size
+    fn format_bytes(bytes: u64) -> String {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} {}", bytes, units[0])
+        } else {
+            format!("{:.1} {}", size, units[unit])
+        }
+    }
+
+    fn handle_healthcheck(&self) -> Result<()> {
+        self.log_info("Running system health checks...", None);
+
+        let checks = vec![
+            ("Flake valid", "nix flake check --no-build"),
+            ("Git clean", "git status --porcelain"),
+            ("Disk space", "df -h / | tail -1"),
+            (
+                "Nix store",
+                "nix store optimise --dry-run 2>&1 | grep 'freed'",
+            ),
+        ];
+
+        println!();
+        for (name, cmd) in checks {
+            print!("  {} ... ", name.cyan());
+            io::stdout().flush()?;
+
+            let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+
+            if output.status.success() {
+                println!("{}", "✓".green());
+            } else {
+                println!("{}", "✗".red());
+                if self.verbose {
+                    println!(
+                        "    {}",
+                        String::from_utf8_lossy(&output.stderr).trim().dimmed()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle rebuild command
+    fn handle_rebuild(
+        &self,
+        host: Option<&str>,
+        execute: bool,
+        command_only: bool,
+        deploy: &DeployArgs,
+        dirty: &DirtyArgs,
+    ) -> Result<()> {
+        let host_name = self.resolve_host(host)?;
+        let (cmd, target, build) = self.nixos_rebuild_command("switch", &host_name, deploy)?;
 
         if command_only {
             println!("{}", cmd);
             return Ok(());
         }
 
-        self.handle_command_flow(
+        if execute && !self.guard_dirty_tree("rebuild", &host_name, dirty)? {
+            return Ok(());
+        }
+
+        if execute {
+            for destination in target.iter().chain(&build) {
+                self.check_ssh(destination)?;
+            }
+        }
+
+        let started = Instant::now();
+        let action_desc =
+            Self::deploy_description("Rebuilding system", &host_name, target.as_deref());
+        let result = self.handle_command_flow(
             &cmd,
             execute,
-            "Rebuilding system",
+            &action_desc,
             &self.config.hooks.pre_rebuild,
             &self.config.hooks.post_rebuild,
-        )?;
+        );
+        //> Nothing to report when the rebuild was only printed or got cancelled
+        if !matches!(result, Ok(false)) {
+            self.notify_done(
+                "rebuild",
+                started,
+                &format!("Rebuilding {}", host_name),
+                &result,
+            );
+        }
 
-        Ok(())
+        result.map(|_| ())
     }
 
     /// Handle test command
-    fn handle_test(&self, host: Option<&str>, execute: bool) -> Result<()> {
-        let host_name = host
-            .map(String::from)
-            .unwrap_or_else(Self::get_current_host);
-        let cmd = format!("sudo nixos-rebuild test --flake .#{}", host_name);
+    fn handle_test(
+        &self,
+        host: Option<&str>,
+        execute: bool,
+        deploy: &DeployArgs,
+        dirty: &DirtyArgs,
+    ) -> Result<()> {
+        let host_name = self.resolve_host(host)?;
+        let (cmd, target, build) = self.nixos_rebuild_command("test", &host_name, deploy)?;
+
+        if execute && !self.guard_dirty_tree("test", &host_name, dirty)? {
+            return Ok(());
+        }
+
+        if execute {
+            for destination in target.iter().chain(&build) {
+                self.check_ssh(destination)?;
+            }
+        }
 
         self.handle_command_flow(
             &cmd,
             execute,
-            "Testing configuration",
+            &Self::deploy_description("Testing configuration", &host_name, target.as_deref()),
             &[], // no pre hooks
             &[], // no post hooks
         )?;
@@ -1375,23 +4036,267 @@ impl DotDots {
     }
 
     /// Handle boot command
-    fn handle_boot(&self, host: Option<&str>, execute: bool) -> Result<()> {
-        let host_name = host
-            .map(String::from)
-            .unwrap_or_else(Self::get_current_host);
-        let cmd = format!("sudo nixos-rebuild boot --flake .#{}", host_name);
+    fn handle_boot(
+        &self,
+        host: Option<&str>,
+        execute: bool,
+        deploy: &DeployArgs,
+        dirty: &DirtyArgs,
+    ) -> Result<()> {
+        let host_name = self.resolve_host(host)?;
+        let (cmd, target, build) = self.nixos_rebuild_command("boot", &host_name, deploy)?;
 
-        self.handle_command_flow(&cmd, execute, "Building boot configuration", &[], &[])?;
+        if execute && !self.guard_dirty_tree("boot", &host_name, dirty)? {
+            return Ok(());
+        }
+
+        if execute {
+            for destination in target.iter().chain(&build) {
+                self.check_ssh(destination)?;
+            }
+        }
+
+        let action_desc =
+            Self::deploy_description("Building boot configuration", &host_name, target.as_deref());
+        self.handle_command_flow(&cmd, execute, &action_desc, &[], &[])?;
+
+        Ok(())
+    }
+
+    /// Helper: Deal with uncommitted changes before building, returning whether to go on
+    ///
+    /// Flakes only see files git knows about, so untracked modules would be
+    /// silently left out. Flags win over `options.dirty_tree`, which can be set
+    /// per host in `[hosts.<name>.options]`.
+    fn guard_dirty_tree(&self, action: &str, host: &str, args: &DirtyArgs) -> Result<bool> {
+        let configured = self
+            .config
+            .hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .and_then(|(_, profile)| profile.options.as_ref())
+            .map(|options| options.dirty_tree.as_str())
+            .filter(|policy| !policy.trim().is_empty())
+            .unwrap_or(&self.config.options.dirty_tree);
+        let policy = if args.allow_dirty {
+            DirtyPolicy::Allow
+        } else if args.stage_new {
+            DirtyPolicy::Stage
+        } else if args.commit {
+            DirtyPolicy::Commit
+        } else {
+            DirtyPolicy::parse(configured, DirtyPolicy::Warn)
+        };
+
+        if !self.is_git_repo(&self.root)? {
+            return Ok(true);
+        }
+        let changes = self.get_git_changes(&self.root)?;
+        if changes == 0 {
+            return Ok(true);
+        }
+
+        let git = |args: &[&str]| -> Result<std::process::Output> {
+            Command::new("git")
+                .arg("-C")
+                .arg(&self.root)
+                .args(args)
+                .output()
+                .context("Failed to run git")
+        };
+        let output = git(&["ls-files", "--others", "--exclude-standard", "--", "*.nix"])?;
+        let untracked: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect();
+
+        match policy {
+            DirtyPolicy::Allow => Ok(true),
+            DirtyPolicy::Stage => {
+                if !untracked.is_empty() {
+                    let mut args = vec!["add", "--"];
+                    args.extend(untracked.iter().map(String::as_str));
+                    let output = git(&args)?;
+                    if !output.status.success() {
+                        anyhow::bail!(
+                            "Failed to stage new files: {}",
+                            String::from_utf8_lossy(&output.stderr).trim()
+                        );
+                    }
+                    self.log_success(
+                        &format!("Staged {} new .nix file(s)", untracked.len()),
+                        None,
+                    );
+                }
+                Ok(true)
+            }
+            DirtyPolicy::Commit => {
+                let message = format!(
+                    "{} {} {}",
+                    action,
+                    host,
+                    Local::now().format("%Y-%m-%d %H:%M")
+                );
+
+                //> Everything gets committed, not just .nix files, so show it first
+                let output = git(&["status", "--porcelain"])?;
+                println!("\n{}", "Uncommitted changes to be committed:".yellow());
+                for line in String::from_utf8_lossy(&output.stdout).lines() {
+                    println!("   {}", line.yellow());
+                }
+                let cmd = format!("git add -A && git commit -m \"{}\"", message);
+                if !self.confirm_destructive(&cmd)? {
+                    return Ok(false);
+                }
+
+                for args in [vec!["add", "-A"], vec!["commit", "-m", &message]] {
+                    let output = git(&args)?;
+                    if !output.status.success() {
+                        anyhow::bail!(
+                            "git {} failed: {}",
+                            args[0],
+                            String::from_utf8_lossy(&output.stderr).trim()
+                        );
+                    }
+                }
+                self.log_success(
+                    &format!("Committed {} change(s): {}", changes, message),
+                    None,
+                );
+                Ok(true)
+            }
+            DirtyPolicy::Warn if untracked.is_empty() => {
+                self.log_warn(
+                    &format!(
+                        "Building from a dirty tree ({} uncommitted change(s))",
+                        changes
+                    ),
+                    None,
+                )?;
+                Ok(true)
+            }
+            DirtyPolicy::Warn => {
+                self.log_warn(
+                    "Untracked .nix files are invisible to the flake and will not be built:",
+                    None,
+                )?;
+                for file in &untracked {
+                    println!("   {}", file.yellow());
+                }
+                println!(
+                    "{}",
+                    "   Use --stage-new, --commit or --allow-dirty, or set options.dirty_tree"
+                        .dimmed()
+                );
+
+                if !self.confirm("Build without them?")? {
+                    self.log_info("Cancelled", None);
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Helper: nixos-rebuild command for a host, plus its ssh target and build host
+    ///
+    /// Flags win over `[hosts.<name>.deploy]`, which only applies when the
+    /// host being built isn't this machine. Only a plain local rebuild runs under
+    /// local sudo, so ssh to a build or target host keeps the user's keys.
+    fn nixos_rebuild_command(
+        &self,
+        action: &str,
+        host: &str,
+        args: &DeployArgs,
+    ) -> Result<(String, Option<String>, Option<String>)> {
+        let is_local = host == self.current_flake_host();
+        let profile = self
+            .config
+            .hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .and_then(|(_, profile)| profile.deploy.clone())
+            .filter(|_| !is_local)
+            .unwrap_or_default();
+
+        let target = args.target_host.clone().or(profile.target_host);
+        let build = args.build_host.clone().or(profile.build_host);
+        let remote_sudo = args.use_remote_sudo || profile.use_remote_sudo;
+
+        let mut cmd = format!("nixos-rebuild {} --flake .#{}", action, host);
+        if let Some(target) = &target {
+            cmd.push_str(&format!(" --target-host {}", Self::shell_quote(target)));
+        }
+        if let Some(build) = &build {
+            cmd.push_str(&format!(" --build-host {}", Self::shell_quote(build)));
+        }
+
+        if target.is_none() && build.is_none() {
+            cmd.insert_str(0, "sudo ");
+        }
+        if remote_sudo {
+            if target.is_some() {
+                cmd.push_str(" --use-remote-sudo");
+            } else {
+                self.log_warn(
+                    "--use-remote-sudo has no effect without a target host, ignoring it",
+                    None,
+                )?;
+            }
+        }
+
+        Ok((cmd, target, build))
+    }
+
+    /// Helper: Action description naming the ssh target, if any
+    fn deploy_description(action: &str, host: &str, target: Option<&str>) -> String {
+        match target {
+            Some(target) => format!("{} {} on {}", action, host, target),
+            None => action.to_string(),
+        }
+    }
+
+    /// Helper: Fail fast when a deploy target or build host can't be reached over ssh
+    fn check_ssh(&self, destination: &str) -> Result<()> {
+        self.log_info(&format!("Checking ssh access to {}", destination), None);
+        let status = Command::new("ssh")
+            .args([
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ConnectTimeout=5",
+                destination,
+                "true",
+            ])
+            .stdin(Stdio::null())
+            .status()
+            .context("Failed to run ssh")?;
 
+        if !status.success() {
+            anyhow::bail!(
+                "Cannot reach {} over ssh (check the host, keys and ssh-agent)",
+                destination
+            );
+        }
         Ok(())
     }
 
+    /// Helper: Quote a value for `sh -c` unless it is plainly safe
+    fn shell_quote(value: &str) -> String {
+        if !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./-_[]".contains(c))
+        {
+            value.to_string()
+        } else {
+            format!("'{}'", value.replace('\'', "'\\''"))
+        }
+    }
+
     /// Handle dry command
     fn handle_dry(&self, host: Option<&str>, execute: bool) -> Result<()> {
-        let host_name = match host {
-            Some(h) => h.to_string(),
-            None => Self::get_current_host(),
-        };
+        let host_name = self.resolve_host(host)?;
         let cmd = format!("sudo nixos-rebuild dry-build --flake .#{}", host_name);
 
         self.handle_command_flow(&cmd, execute, "Building boot configuration", &[], &[])?;
@@ -1409,6 +4314,197 @@ impl DotDots {
         Ok(())
     }
 
+    /// Handle build command: build each host's toplevel in parallel
+    fn handle_build(&self, all: bool, hosts: &[String], jobs: Option<usize>) -> Result<()> {
+        let hosts: Vec<String> = if all {
+            self.host_names(false)?
+        } else if hosts.is_empty() {
+            vec![self.resolve_host(None)?]
+        } else {
+            hosts
+                .iter()
+                .map(|host| self.resolve_host(Some(host)))
+                .collect::<Result<_>>()?
+        };
+        if hosts.is_empty() {
+            anyhow::bail!("No hosts to build");
+        }
+
+        let started = Instant::now();
+        let jobs = match jobs.unwrap_or(self.config.options.jobs) {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            jobs => jobs,
+        };
+        self.log_info(
+            &format!("Building {} host(s), {} at a time", hosts.len(), jobs),
+            None,
+        );
+
+        let commands: Vec<(String, String)> = hosts
+            .iter()
+            .map(|host| {
+                let cmd = format!(
+                    "nix build {} --no-link --print-out-paths",
+                    Self::shell_quote(&format!(
+                        ".#nixosConfigurations.{}.config.system.build.toplevel",
+                        Self::nix_string(host)
+                    ))
+                );
+                (cmd, host.clone())
+            })
+            .collect();
+        let results = self.execute_parallel(commands, jobs);
+
+        //> Keep the latest build per host so `dots hosts` can tell when it's outdated
+        let builds_dir = self.cache_dir.join("builds");
+        fs::create_dir_all(&builds_dir)?;
+        for result in &results {
+            let record = serde_json::json!({
+                "host": result.description,
+                "success": result.success,
+                "outPath": result.stdout.lines().last().filter(|_| result.success),
+                "error": (!result.success).then(|| result.failure_reason()),
+                "duration": result.duration.as_secs_f64(),
+                "timestamp": Local::now().to_rfc3339(),
+            });
+            let path = builds_dir.join(format!("{}.json", result.description));
+            fs::write(&path, serde_json::to_string_pretty(&record)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+
+        let width = hosts
+            .iter()
+            .map(|host| host.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        println!();
+        println!(
+            "{}",
+            format!(
+                "{:<width$}  {:<6}  {:>8}  {}",
+                "HOST", "STATUS", "TIME", "RESULT"
+            )
+            .bold()
+            .cyan()
+        );
+        for result in &results {
+            let (status, detail) = if result.success {
+                (
+                    format!("{:<6}", "ok").green(),
+                    result.stdout.lines().last().unwrap_or_default().to_string(),
+                )
+            } else {
+                (format!("{:<6}", "failed").red(), result.failure_reason())
+            };
+            println!(
+                "{:<width$}  {}  {:>7.1}s  {}",
+                result.description,
+                status,
+                result.duration.as_secs_f64(),
+                detail
+            );
+        }
+        println!();
+
+        let failed = results.iter().filter(|result| !result.success).count();
+        let outcome = if failed > 0 {
+            Err(anyhow::anyhow!(
+                "{} of {} host(s) failed to build",
+                failed,
+                results.len()
+            ))
+        } else {
+            Ok(())
+        };
+        self.notify_done(
+            "build",
+            started,
+            &format!("Building {} host(s)", results.len()),
+            &outcome,
+        );
+        outcome?;
+        self.log_success(&format!("Built {} host(s)", results.len()), None);
+
+        Ok(())
+    }
+
+    /// Handle home command: drive Home Manager for `homeConfigurations`
+    fn handle_home(&self, action: HomeAction, target: Option<&str>, execute: bool) -> Result<()> {
+        let target = self.resolve_home(target)?;
+        let hooks = &self.config.hooks;
+        let (verb, action_desc, pre_hooks, post_hooks) = match action {
+            HomeAction::Switch => (
+                "switch",
+                "Switching home configuration",
+                hooks.pre_home.as_slice(),
+                hooks.post_home.as_slice(),
+            ),
+            HomeAction::Build => (
+                "build",
+                "Building home configuration",
+                hooks.pre_home.as_slice(),
+                hooks.post_home.as_slice(),
+            ),
+            HomeAction::News => ("news", "Reading Home Manager news", &[][..], &[][..]),
+        };
+        let cmd = format!("home-manager {} --flake .#{}", verb, target);
+
+        self.handle_command_flow(&cmd, execute, action_desc, pre_hooks, post_hooks)?;
+
+        Ok(())
+    }
+
+    /// Resolve and validate a `homeConfigurations` entry, defaulting to `$USER@<host>`
+    ///
+    /// Flakes key homes as either `user@host` or just `user`, so a bare user
+    /// entry is accepted for any host.
+    fn resolve_home(&self, target: Option<&str>) -> Result<String> {
+        let requested = match target {
+            Some(target) => target.to_string(),
+            None => format!(
+                "{}@{}",
+                env::var("USER").unwrap_or_default(),
+                self.current_flake_host()
+            ),
+        };
+
+        let expr =
+            "builtins.attrNames ((builtins.getFlake (toString ./.)).homeConfigurations or { })";
+        let json = self.nix_eval(expr).with_context(|| {
+            format!(
+                "Could not validate home {} against homeConfigurations",
+                requested
+            )
+        })?;
+        let names: Vec<String> = serde_json::from_value(json).unwrap_or_default();
+        if names.is_empty() {
+            anyhow::bail!("No homeConfigurations found in the flake");
+        }
+
+        let user = requested.split('@').next().unwrap_or_default();
+        let found = names
+            .iter()
+            .find(|name| **name == requested)
+            .or_else(|| {
+                names
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(&requested))
+            })
+            .or_else(|| names.iter().find(|name| name.as_str() == user));
+        if let Some(found) = found {
+            return Ok(found.clone());
+        }
+
+        let mut message = format!("Unknown home configuration: {}", requested);
+        let close = Self::close_matches(&requested, &names);
+        if !close.is_empty() {
+            message.push_str(&format!("\nDid you mean: {}?", close.join(", ")));
+        }
+        message.push_str(&format!("\nAvailable homes: {}", names.join(", ")));
+        anyhow::bail!(message)
+    }
+
     /// Handle update command
     fn handle_update(&self, execute: bool, input: Option<&str>) -> Result<()> {
         let resolved_input = self.resolve_alias(input.unwrap_or(""));
@@ -1417,15 +4513,20 @@ impl DotDots {
         } else {
             "nix flake update".to_string()
         };
-        self.handle_command_flow(
+
+        let started = Instant::now();
+        let result = self.handle_command_flow(
             &cmd,
             execute,
             "Updating flake",
             &self.config.hooks.pre_update,
             &self.config.hooks.post_update,
-        )?;
+        );
+        if !matches!(result, Ok(false)) {
+            self.notify_done("update", started, "Updating flake", &result);
+        }
 
-        Ok(())
+        result.map(|_| ())
     }
 
     /// Handle binit command
@@ -1433,7 +4534,7 @@ impl DotDots {
         let bin_dirs = self.find_bin_directories()?;
 
         if bin_dirs.is_empty() {
-            self.log_warn("No bin directories found", None);
+            self.log_warn("No bin directories found", None)?;
             return Ok(());
         }
 
@@ -1557,34 +4658,73 @@ impl DotDots {
     /// Handle check command
     fn handle_check(&self, fix: bool, strict: bool) -> Result<()> {
         self.log_info("Running checks in parallel...", None);
+        let started = Instant::now();
 
-        let checks = vec![
+        let checks: Vec<(String, String)> = [
             ("treefmt --fail-on-change", "Format check"),
             ("nix flake check 2>&1 | head -20", "Flake check"),
-        ];
-
-        let results = self.execute_parallel(checks)?;
-
-        let mut failed = Vec::new();
-        for (i, result) in results.iter().enumerate() {
-            match result {
-                Ok(_) => self.log_success(&format!("✓ Check {} passed", i + 1), None),
-                Err(e) => {
-                    self.log_error(&format!("✗ Check {} failed: {}", i + 1, e), None);
-                    failed.push(e);
+        ]
+        .iter()
+        .map(|(cmd, desc)| (cmd.to_string(), desc.to_string()))
+        .collect();
+        let jobs = checks.len();
+
+        //> Descriptions of the checks that failed, after reporting each one
+        let report = |results: &[ParallelResult]| -> Vec<String> {
+            let mut failed = Vec::new();
+            for (i, result) in results.iter().enumerate() {
+                if result.success {
+                    self.log_success(&format!("✓ Check {} passed", i + 1), None);
+                } else {
+                    self.show_error(&format!(
+                        "✗ Check {} failed: {}: {}",
+                        i + 1,
+                        result.description,
+                        result.stderr
+                    ));
+                    failed.push(result.description.clone());
                 }
             }
-        }
+            failed
+        };
 
-        if !failed.is_empty() {
-            if fix {
-                self.log_info("Attempting to fix issues...", None);
-                self.execute("treefmt", "treefmt", None)?;
-            } else if strict {
-                anyhow::bail!("Strict mode: {} checks failed", failed.len());
-            }
+        let failed = report(&self.execute_parallel(checks.clone(), jobs));
+
+        //> With --fix, the failed checks run again so the outcome reflects the fix
+        let remaining = if !failed.is_empty() && fix {
+            self.log_info("Attempting to fix issues...", None);
+            self.execute("treefmt", "treefmt", None).map(|_| {
+                let retry: Vec<(String, String)> = checks
+                    .into_iter()
+                    .filter(|(_, description)| failed.contains(description))
+                    .collect();
+                report(&self.execute_parallel(retry, jobs))
+            })
         } else {
+            Ok(failed)
+        };
+
+        let outcome = match &remaining {
+            Ok(remaining) if remaining.is_empty() => Ok(()),
+            Ok(remaining) => Err(anyhow::anyhow!(
+                "{} of {} checks failed",
+                remaining.len(),
+                jobs
+            )),
+            Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+        };
+        self.notify_done("check", started, "Checks", &outcome);
+
+        let remaining = remaining?;
+        if remaining.is_empty() {
             self.log_success("All checks passed!", None);
+        } else if strict {
+            anyhow::bail!("Strict mode: {} checks failed", remaining.len());
+        } else {
+            self.log_error(
+                &format!("{} of {} checks failed", remaining.len(), jobs),
+                None,
+            )?;
         }
 
         Ok(())
@@ -1612,7 +4752,7 @@ impl DotDots {
             if prompt {
                 return Ok(());
             }
-            self.log_error("Not a git repository", None);
+            self.log_error("Not a git repository", None)?;
             return Ok(());
         }
 
@@ -1648,6 +4788,42 @@ impl DotDots {
             println!("\n{}", " Repository is syncronized".magenta().bold());
         }
 
+        if !self.config.includes.is_empty() {
+            self.show_includes_status()?;
+        }
+
+        Ok(())
+    }
+
+    /// Show includes and, for those that are separate repositories, their git state
+    fn show_includes_status(&self) -> Result<()> {
+        self.log_header("Includes", None);
+
+        for include in &self.config.includes {
+            let path = Self::resolve_include_path(&self.root, &include.path);
+            let modules = if include.modules.is_empty() {
+                "all".to_string()
+            } else {
+                include.modules.join(", ")
+            };
+
+            let state = if !include.enabled {
+                "disabled".dimmed()
+            } else if !path.is_dir() {
+                "missing".red()
+            } else if !path.starts_with(&self.root) && self.is_git_repo(&path)? {
+                let branch = self.get_git_branch(&path)?;
+                match self.get_git_changes(&path)? {
+                    0 => format!("[{}]", branch).green(),
+                    changes => format!("[{} +{}]", branch, changes).yellow(),
+                }
+            } else {
+                "enabled".green()
+            };
+
+            println!("  {} {} ({})", include.path.cyan(), state, modules.dimmed());
+        }
+
         Ok(())
     }
 
@@ -1662,7 +4838,10 @@ impl DotDots {
         if let Some(expr) = expr {
             cmd.push_str(&format!(" --expr '{}'", expr));
         } else {
-            cmd.push_str(" --file \"$DOTS/default.nix\"");
+            cmd.push_str(&format!(
+                " --file \"{}\"",
+                self.root.join("default.nix").display()
+            ));
         }
 
         println!("{}", cmd.bright_white());
@@ -1677,25 +4856,513 @@ impl DotDots {
         Ok(())
     }
 
-    /// Rollback to previous generation
-    fn handle_rollback(&self, execute: bool) -> Result<()> {
-        // Get current generation
-        let current_gen = Command::new("sh")
-        .arg("-c")
-        .arg("sudo nix-env --list-generations --profile /nix/var/nix/profiles/system | tail -2 | head -1 | awk '{print $1}'")
-        .output()?;
-
-        let current = String::from_utf8_lossy(&current_gen.stdout)
-            .trim()
-            .parse::<i32>()
-            .unwrap_or(0);
+    /// Saved build logs, oldest first
+    fn build_logs(&self) -> Result<Vec<BuildLog>> {
+        let dir = self.logs_dir.join("builds");
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut logs = Vec::new();
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("log") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            let fields = content
+                .lines()
+                .filter_map(|line| line.strip_prefix("# ")?.split_once(": "))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            logs.push(BuildLog {
+                id: path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                path,
+                fields,
+            });
+        }
+
+        //> Ids start with a timestamp, so they sort chronologically
+        logs.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(logs)
+    }
+
+    /// Handle logs command
+    fn handle_logs(&self, action: Option<&LogsAction>) -> Result<()> {
+        let logs = self.build_logs()?;
+        let show = |log: &BuildLog| -> Result<()> {
+            println!("{}", log.path.display().to_string().dimmed());
+            print!("{}", fs::read_to_string(&log.path)?);
+            Ok(())
+        };
+
+        match action.unwrap_or(&LogsAction::List { limit: None }) {
+            LogsAction::List { limit } => {
+                if logs.is_empty() {
+                    self.log_info("No build logs yet", None);
+                    return Ok(());
+                }
+
+                let skip = limit.map_or(0, |limit| logs.len().saturating_sub(limit));
+                let id_width = logs.iter().map(|log| log.id.len()).max().unwrap_or(0);
+                println!(
+                    "{}",
+                    format!(
+                        "{:<id_width$}  {:<4}  {:<8}  {}",
+                        "ID",
+                        "EXIT",
+                        "DURATION",
+                        "REVISION",
+                        id_width = id_width
+                    )
+                    .bold()
+                    .cyan()
+                );
+                for log in logs.iter().skip(skip).rev() {
+                    let exit = log.field("exit");
+                    println!(
+                        "{:<id_width$}  {:<4}  {:<8}  {}",
+                        log.id,
+                        match exit {
+                            "0" => exit.green(),
+                            "-" => exit.yellow(),
+                            _ => exit.red(),
+                        },
+                        log.field("duration"),
+                        log.field("revision").dimmed(),
+                        id_width = id_width
+                    );
+                }
+            }
+            LogsAction::Show { id } => {
+                let matches: Vec<&BuildLog> = match logs.iter().find(|log| &log.id == id) {
+                    Some(log) => vec![log],
+                    None => logs
+                        .iter()
+                        .filter(|log| log.id.contains(id.as_str()))
+                        .collect(),
+                };
+                match matches.as_slice() {
+                    [log] => show(log)?,
+                    [] => anyhow::bail!("No build log matches '{}'", id),
+                    _ => anyhow::bail!(
+                        "'{}' matches several build logs: {}",
+                        id,
+                        matches
+                            .iter()
+                            .map(|log| log.id.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                }
+            }
+            LogsAction::Last => match logs.last() {
+                Some(log) => show(log)?,
+                None => self.log_info("No build logs yet", None),
+            },
+            LogsAction::Grep {
+                pattern,
+                insensitive,
+            } => {
+                let regex = regex::RegexBuilder::new(pattern)
+                    .case_insensitive(*insensitive)
+                    .build()
+                    .with_context(|| format!("Invalid pattern '{}'", pattern))?;
+
+                let mut found = 0;
+                for log in logs.iter().rev() {
+                    let content = fs::read_to_string(&log.path)?;
+                    for (number, line) in content.lines().enumerate() {
+                        if !line.starts_with("# ") && regex.is_match(line) {
+                            found += 1;
+                            println!("{}:{}: {}", log.id.cyan(), number + 1, line);
+                        }
+                    }
+                }
+                if found == 0 {
+                    self.log_info("No matches", None);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the system profile's generation links into structured data
+    fn system_generations(&self) -> Result<Vec<Generation>> {
+        Self::profile_generations(Path::new(SYSTEM_PROFILE))
+    }
+
+    /// Helper: Generations of a profile, from its `<name>-<n>-link` siblings
+    fn profile_generations(profile: &Path) -> Result<Vec<Generation>> {
+        let prefix = format!(
+            "{}-",
+            profile.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let profiles_dir = profile.parent().unwrap_or(Path::new("/"));
+        let current_link = fs::read_link(profile).ok();
+        let current_name = current_link
+            .as_deref()
+            .and_then(|link| link.file_name())
+            .map(|name| name.to_string_lossy().to_string());
+
+        let entries = fs::read_dir(profiles_dir).with_context(|| {
+            format!(
+                "Failed to read system profiles in {}",
+                profiles_dir.display()
+            )
+        })?;
+
+        let mut generations = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(number) = name
+                .strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.strip_suffix("-link"))
+                .and_then(|number| number.parse::<u32>().ok())
+            else {
+                continue;
+            };
+
+            let path = entry.path();
+            let date = fs::symlink_metadata(&path)
+                .and_then(|meta| meta.modified())
+                .map(DateTime::<Local>::from)
+                .unwrap_or_else(|_| Local::now());
+            let nixos_version = fs::read_to_string(path.join("nixos-version"))
+                .ok()
+                .map(|version| version.trim().to_string());
+            let kernel = fs::read_dir(path.join("kernel-modules/lib/modules"))
+                .ok()
+                .and_then(|modules| modules.flatten().next())
+                .map(|module| module.file_name().to_string_lossy().to_string());
+
+            generations.push(Generation {
+                number,
+                date,
+                current: current_name.as_deref() == Some(name.as_str()),
+                path,
+                nixos_version,
+                kernel,
+            });
+        }
+
+        if generations.is_empty() {
+            anyhow::bail!("No system generations found in {}", profiles_dir.display());
+        }
+        generations.sort_by_key(|generation| generation.number);
+        Ok(generations)
+    }
+
+    /// Helper: Expand a generation spec like `3,5,8-10` into numbers
+    fn parse_generation_range(spec: &str) -> Result<Vec<u32>> {
+        let mut numbers = BTreeSet::new();
+        for part in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let parse = |number: &str| {
+                number
+                    .trim()
+                    .parse::<u32>()
+                    .with_context(|| format!("Invalid generation number '{}'", number.trim()))
+            };
+            match part.split_once('-').or_else(|| part.split_once("..")) {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        anyhow::bail!("Invalid generation range '{}'", part);
+                    }
+                    numbers.extend(start..=end);
+                }
+                None => {
+                    numbers.insert(parse(part)?);
+                }
+            }
+        }
+
+        if numbers.is_empty() {
+            anyhow::bail!("No generations given in '{}'", spec);
+        }
+        Ok(numbers.into_iter().collect())
+    }
+
+    /// Handle generations command
+    fn handle_generations(&self, action: Option<&GenerationsAction>) -> Result<()> {
+        let generations = self.system_generations()?;
+        let find = |number: u32| {
+            generations
+                .iter()
+                .find(|generation| generation.number == number)
+                .with_context(|| format!("Generation {} does not exist", number))
+        };
+
+        match action.unwrap_or(&GenerationsAction::List { json: false }) {
+            GenerationsAction::List { json } => {
+                if *json {
+                    let rows: Vec<serde_json::Value> = generations
+                        .iter()
+                        .map(|generation| {
+                            serde_json::json!({
+                                "number": generation.number,
+                                "date": generation.date.to_rfc3339(),
+                                "current": generation.current,
+                                "path": generation.path,
+                                "nixosVersion": generation.nixos_version,
+                                "kernel": generation.kernel,
+                            })
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&rows)?);
+                    return Ok(());
+                }
+
+                let version_width = generations
+                    .iter()
+                    .filter_map(|generation| generation.nixos_version.as_ref())
+                    .map(|version| version.len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(5);
+                println!(
+                    "{}",
+                    format!(
+                        "{:<6}  {:<19}  {:<version_width$}  {}",
+                        "GEN",
+                        "DATE",
+                        "NIXOS",
+                        "KERNEL",
+                        version_width = version_width
+                    )
+                    .bold()
+                    .cyan()
+                );
+                for generation in &generations {
+                    let number = if generation.current {
+                        format!("{} *", generation.number)
+                    } else {
+                        generation.number.to_string()
+                    };
+                    println!(
+                        "{:<6}  {:<19}  {:<version_width$}  {}",
+                        if generation.current {
+                            number.green().bold()
+                        } else {
+                            number.green()
+                        },
+                        generation.date.format("%Y-%m-%d %H:%M:%S"),
+                        generation.nixos_version.as_deref().unwrap_or("-"),
+                        generation.kernel.as_deref().unwrap_or("-"),
+                        version_width = version_width
+                    );
+                }
+                println!("\n{}", "* current generation".dimmed());
+            }
+            GenerationsAction::Diff { a, b } => {
+                let (old, new) = (find(*a)?, find(*b)?);
+                self.print_closure_diff(&old.path, &new.path)?;
+            }
+            GenerationsAction::Delete { range, execute } => {
+                let numbers = Self::parse_generation_range(range)?;
+                for number in &numbers {
+                    if find(*number)?.current {
+                        anyhow::bail!("Refusing to delete the current generation {}", number);
+                    }
+                }
+
+                let numbers: Vec<String> = numbers.iter().map(u32::to_string).collect();
+                let cmd = format!(
+                    "sudo nix-env --profile {} --delete-generations {}",
+                    SYSTEM_PROFILE,
+                    numbers.join(" ")
+                );
+                self.handle_command_flow(&cmd, *execute, "Deleting generations", &[], &[])?;
+            }
+            GenerationsAction::Keep { count, execute } => {
+                if *count == 0 {
+                    anyhow::bail!("Keep at least one generation");
+                }
+
+                //> nix-env keeps the newest N generations and always the current one
+                let keep_from = generations.len().saturating_sub(*count as usize);
+                let doomed: Vec<String> = generations[..keep_from]
+                    .iter()
+                    .filter(|generation| !generation.current)
+                    .map(|generation| generation.number.to_string())
+                    .collect();
+                if doomed.is_empty() {
+                    self.log_info("Nothing to delete", None);
+                    return Ok(());
+                }
+                println!(
+                    "{}",
+                    format!("Deleting generations: {}", doomed.join(", ")).yellow()
+                );
+
+                let cmd = format!(
+                    "sudo nix-env --profile {} --delete-generations +{}",
+                    SYSTEM_PROFILE, count
+                );
+                self.handle_command_flow(&cmd, *execute, "Deleting generations", &[], &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Helper: Git commit a generation was built from, as `(commit, exact)`
+    ///
+    /// Uses the `system.configurationRevision` baked into the generation and
+    /// falls back to the last commit before it was built, marked inexact.
+    fn generation_commit(&self, generation: &Generation) -> Option<(String, bool)> {
+        let revision = Command::new(generation.path.join("sw/bin/nixos-version"))
+            .arg("--configuration-revision")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|revision| !revision.is_empty());
+
+        let (rev, exact) = match &revision {
+            Some(revision) => (revision.trim_end_matches("-dirty").to_string(), true),
+            None => (format!("--before={}", generation.date.to_rfc3339()), false),
+        };
+        let output = Command::new("git")
+            .args(["-C", &self.root.to_string_lossy(), "log", "-1"])
+            .args(["--format=%h %s", &rev])
+            .output()
+            .ok()?;
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        match (commit.is_empty(), revision) {
+            (false, _) => Some((commit, exact)),
+            //> Built from a commit this checkout does not have
+            (true, Some(revision)) => Some((revision.chars().take(12).collect(), true)),
+            (true, None) => None,
+        }
+    }
+
+    /// Helper: Ask which generation to roll back to
+    fn pick_generation(&self, generations: &[Generation]) -> Result<Option<u32>> {
+        self.log_header("Generations", None);
+        for generation in generations.iter().rev() {
+            let commit = match self.generation_commit(generation) {
+                Some((commit, true)) => commit,
+                Some((commit, false)) => format!("~{}", commit),
+                None => "-".to_string(),
+            };
+            let number = format!("{:>4}", generation.number);
+            println!(
+                "  [{}] {}  {}  {}{}",
+                if generation.current {
+                    number.green().bold()
+                } else {
+                    number.cyan()
+                },
+                generation.date.format("%Y-%m-%d %H:%M"),
+                generation.nixos_version.as_deref().unwrap_or("-").dimmed(),
+                commit,
+                if generation.current { " (current)" } else { "" }.green()
+            );
+        }
+        println!("\n{}", "~ nearest commit before the build".dimmed());
+
+        print!(
+            "\n{}",
+            "Roll back to generation (empty to cancel): ".yellow()
+        );
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let choice = input.trim();
+        if choice.is_empty() {
+            return Ok(None);
+        }
+
+        choice
+            .parse::<u32>()
+            .map(Some)
+            .with_context(|| format!("Invalid generation number '{}'", choice))
+    }
+
+    /// Rollback to previous generation, or to a chosen one
+    fn handle_rollback(&self, execute: bool, to: Option<u32>, pick: bool) -> Result<()> {
+        let generations = self.system_generations()?;
+        let current = generations
+            .iter()
+            .find(|generation| generation.current)
+            .map(|generation| generation.number)
+            .context("Could not determine the current system generation")?;
+
+        let target = match (to, pick) {
+            (Some(number), _) => Some(number),
+            (None, true) => match self.pick_generation(&generations)? {
+                Some(number) => Some(number),
+                None => {
+                    self.log_info("Cancelled", None);
+                    return Ok(());
+                }
+            },
+            (None, false) => None,
+        };
 
-        if current <= 1 {
-            self.log_warn("Already at oldest generation", None);
+        if let Some(number) = target {
+            let generation = generations
+                .iter()
+                .find(|generation| generation.number == number)
+                .with_context(|| format!("Generation {} does not exist", number))?;
+            if generation.current {
+                self.log_warn(&format!("Generation {} is already current", number), None)?;
+                return Ok(());
+            }
+
+            println!(
+                "{}",
+                format!("Rollback: generation {} → {}", current, number).yellow()
+            );
+
+            //> Point the profile at the generation so it stays the boot default
+            let cmd = format!(
+                "sudo nix-env --profile {profile} --switch-generation {number} && sudo {switch} switch",
+                profile = SYSTEM_PROFILE,
+                number = number,
+                switch = generation
+                    .path
+                    .join("bin/switch-to-configuration")
+                    .display()
+            );
+            let switched = self.handle_command_flow(
+                &cmd,
+                execute,
+                &format!("Rolling back to generation {}", number),
+                &[],
+                &[],
+            )?;
+
+            if switched {
+                self.log_success("Rolled back successfully!", Some(self.icons.build(None)));
+            }
             return Ok(());
         }
 
-        let previous = current - 1;
+        //> `--rollback` switches to the newest generation older than the current one
+        let Some(previous) = generations
+            .iter()
+            .rev()
+            .map(|generation| generation.number)
+            .find(|number| *number < current)
+        else {
+            self.log_warn("Already at oldest generation", None)?;
+            return Ok(());
+        };
 
         // Show rollback info
         println!(
@@ -1705,7 +5372,7 @@ impl DotDots {
 
         let cmd = "sudo nixos-rebuild switch --rollback".to_string();
 
-        self.handle_command_flow(
+        let switched = self.handle_command_flow(
             &cmd,
             execute,
             "Rolling back to previous generation",
@@ -1714,7 +5381,9 @@ impl DotDots {
         )?;
 
         // Custom success message with sync icon
-        self.log_success("Rolled back successfully!", Some(self.icons.build(None)));
+        if switched {
+            self.log_success("Rolled back successfully!", Some(self.icons.build(None)));
+        }
 
         Ok(())
     }
@@ -1738,8 +5407,25 @@ impl DotDots {
         let mut results = Vec::new();
         let mut total_matches = 0;
 
-        for entry in WalkDir::new(&self.root)
+        //> Includes are searched only through their own roots, so `modules` and
+        //> `enabled` still apply when an include lives inside the repo
+        let include_paths: Vec<PathBuf> = self
+            .config
+            .includes
+            .iter()
+            .map(|include| Self::resolve_include_path(&self.root, &include.path))
+            .filter(|path| path.starts_with(&self.root) && *path != self.root)
+            .collect();
+        let repo = WalkDir::new(&self.root)
+            .into_iter()
+            .filter_entry(|e| !include_paths.iter().any(|path| e.path() == path));
+        let includes = self
+            .include_roots()
             .into_iter()
+            .flat_map(|dir| WalkDir::new(dir).into_iter());
+
+        for entry in repo
+            .chain(includes)
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file())
         {
@@ -1790,7 +5476,7 @@ impl DotDots {
         }
 
         if results.is_empty() {
-            self.log_warn("No matches found", None);
+            self.log_warn("No matches found", None)?;
             return Ok(());
         }
 
@@ -1861,32 +5547,1103 @@ impl DotDots {
                 }
             }
 
-            CacheAction::List => {
-                println!("{}", "Cached Files".bold().cyan());
-                println!("{}", "=".repeat(20).dimmed());
+            CacheAction::List => {
+                println!("{}", "Cached Files".bold().cyan());
+                println!("{}", "=".repeat(20).dimmed());
+
+                let list_files = |dir: &Path, prefix: &str| -> Result<()> {
+                    if dir.exists() {
+                        for entry in fs::read_dir(dir)? {
+                            let entry = entry?;
+                            let path = entry.path();
+                            if path.is_file() {
+                                println!("{}{}", prefix, entry.file_name().to_string_lossy());
+                            }
+                        }
+                    }
+                    Ok(())
+                };
+
+                println!("\nLogs:");
+                list_files(&self.logs_dir, "  ")?;
+
+                println!("\nTemp files:");
+                list_files(&self.tmp_dir, "  ")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle config commands
+    fn handle_config(&self, action: &ConfigAction) -> Result<()> {
+        let effective = serde_json::to_value(&self.config)?;
+
+        match action {
+            ConfigAction::Show { origin, json } => {
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&effective)?);
+                    return Ok(());
+                }
+
+                if !origin {
+                    println!("{}", toml::to_string_pretty(&self.config)?);
+                    return Ok(());
+                }
+
+                let mut entries = Vec::new();
+                Self::flatten_config(&effective, "", &mut entries);
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+                //> Align comments, but don't let long vectors push everything right
+                let entry_width = |(key, value): &(String, serde_json::Value)| {
+                    key.len() + value.to_string().len()
+                };
+                let width = entries
+                    .iter()
+                    .map(entry_width)
+                    .filter(|width| *width <= 60)
+                    .max()
+                    .unwrap_or(0);
+
+                for entry in &entries {
+                    let (key, value) = entry;
+                    let padding = " ".repeat(width.saturating_sub(entry_width(entry)));
+                    let origins = self.config_origins(key).join(", ");
+                    println!(
+                        "{} = {}{}  {}",
+                        key.cyan(),
+                        value,
+                        padding,
+                        format!("# {}", origins).dimmed()
+                    );
+                }
+            }
+
+            ConfigAction::Get { key } => match Self::config_lookup(&effective, key) {
+                Some(serde_json::Value::String(value)) => println!("{}", value),
+                Some(value) if value.is_object() || value.is_array() => {
+                    println!("{}", serde_json::to_string_pretty(value)?)
+                }
+                Some(value) => println!("{}", value),
+                None => anyhow::bail!("Unknown config key: {}", key),
+            },
+
+            ConfigAction::Set { key, value, user } => {
+                let kind = if *user {
+                    LayerKind::User
+                } else {
+                    LayerKind::Repo
+                };
+                let target = self
+                    .config_layers
+                    .iter()
+                    .find(|layer| layer.kind == kind)
+                    .and_then(|layer| layer.path.clone())
+                    .unwrap_or_else(|| match kind {
+                        LayerKind::User => dirs::home_dir()
+                            .unwrap_or_default()
+                            .join(".config/dots.toml"),
+                        _ => self.root.join(".dots.toml"),
+                    });
+
+                self.set_config_value(&target, kind, key, value)?;
+                self.log_success(&format!("Set {} in {}", key, target.display()), None);
+            }
+
+            ConfigAction::Validate { strict } => {
+                let mut failed = 0;
+                //> Merge cumulatively so each layer is checked in context
+                for (i, layer) in self.config_layers.iter().enumerate() {
+                    match Self::merge_config_layers(&self.config_layers[..=i]) {
+                        Ok(_) => self.log_success(
+                            &format!("{:<8} {}", layer.kind.label(), layer.origin()),
+                            None,
+                        ),
+                        Err(e) => {
+                            failed += 1;
+                            self.show_error(&format!(
+                                "{:<8} {}: {:#}",
+                                layer.kind.label(),
+                                layer.origin(),
+                                e
+                            ));
+                        }
+                    }
+                }
+
+                if *strict {
+                    for layer in &self.config_layers {
+                        for issue in Self::strict_config_issues(layer)? {
+                            failed += 1;
+                            self.show_error(&issue);
+                        }
+                    }
+                }
+
+                if failed > 0 {
+                    anyhow::bail!("{} config issue(s) found", failed);
+                }
+                self.log_success("Configuration is valid", None);
+            }
+
+            ConfigAction::Schema { output } => {
+                let schema = serde_json::to_string_pretty(&schemars::schema_for!(DotsConfig))?;
+                match output {
+                    Some(path) => {
+                        fs::write(path, schema)
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                        self.log_success(&format!("Schema written to {}", path.display()), None);
+                        println!("\nReference it from .dots.toml for editor completion:");
+                        println!("  #:schema {}", path.display());
+                    }
+                    None => println!("{}", schema),
+                }
+            }
+
+            ConfigAction::Migrate { check, yes } => {
+                let mut pending = 0;
+                for layer in &self.config_layers {
+                    let Some(path) = &layer.path else {
+                        continue;
+                    };
+                    let raw = Self::read_config_file(path)?;
+                    let ops = Self::plan_config_migration(&raw);
+                    if ops.is_empty() {
+                        self.log_success(&format!("{} is up to date", path.display()), None);
+                        continue;
+                    }
+                    pending += 1;
+
+                    let from = Self::config_file_version(&raw);
+                    println!(
+                        "\n{} {} (version {} -> {})",
+                        "Migrating".bold().cyan(),
+                        path.display(),
+                        from,
+                        CONFIG_VERSION
+                    );
+                    for (target, description, _) in &CONFIG_MIGRATIONS {
+                        if u64::from(*target) > from {
+                            println!("  v{}: {}", target, description);
+                        }
+                    }
+
+                    let original = fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read config at {}", path.display()))?;
+                    let migrated = match Self::render_migrated_config(path, &original, &raw, &ops) {
+                        Ok(migrated) => migrated,
+                        Err(e) => {
+                            self.log_warn(&format!("{:#}", e), None)?;
+                            continue;
+                        }
+                    };
+                    println!();
+                    Self::print_diff(&original, &migrated);
+                    println!();
+
+                    if *check {
+                        continue;
+                    }
+                    if !yes && !self.confirm(&format!("Rewrite {}?", path.display()))? {
+                        self.log_info(&format!("Skipped {}", path.display()), None);
+                        continue;
+                    }
+
+                    fs::write(path, &migrated)
+                        .with_context(|| format!("Failed to write config at {}", path.display()))?;
+
+                    //> Put the original back if the rewrite doesn't load
+                    let check = Self::read_config_file(path).and_then(|value| {
+                        serde_json::from_value::<DotsConfig>(value).map_err(anyhow::Error::from)
+                    });
+                    if let Err(e) = check {
+                        fs::write(path, &original)?;
+                        return Err(
+                            e.context(format!("Migration of {} rolled back", path.display()))
+                        );
+                    }
+                    self.log_success(&format!("Migrated {}", path.display()), None);
+                }
+
+                if pending == 0 {
+                    self.log_success("All config files use the current schema", None);
+                } else if *check {
+                    anyhow::bail!("{} config file(s) need `dots config migrate`", pending);
+                }
+            }
+
+            ConfigAction::Path => {
+                println!(
+                    "{}",
+                    "Config layers (lowest to highest precedence):"
+                        .bold()
+                        .cyan()
+                );
+                for layer in &self.config_layers {
+                    println!("  {:<8} {}", layer.kind.label().yellow(), layer.origin());
+                }
+
+                let home = dirs::home_dir().unwrap_or_default();
+                println!("\n{}", "Search paths:".bold().cyan());
+                for dir in [home.clone(), home.join(".config"), self.root.clone()] {
+                    println!("  {}", dir.display());
+                }
+                println!("\n{}", "Filenames:".bold().cyan());
+                println!("  {}", CONFIG_FILENAMES.join(", "));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Helper: Render a config file after migration, keeping TOML comments and layout
+    fn render_migrated_config(
+        path: &Path,
+        original: &str,
+        raw: &serde_json::Value,
+        ops: &[MigrationOp],
+    ) -> Result<String> {
+        match ConfigFormat::from_path(path) {
+            ConfigFormat::Toml => {
+                let mut doc: toml_edit::DocumentMut = original.parse().with_context(|| {
+                    format!("Failed to parse TOML config at {}", path.display())
+                })?;
+                for op in ops {
+                    Self::apply_migration_toml(&mut doc, op)?;
+                }
+                Ok(doc.to_string())
+            }
+            format => {
+                let mut value = raw.clone();
+                for op in ops {
+                    Self::apply_migration_op(&mut value, op);
+                }
+                match format {
+                    ConfigFormat::Json => Ok(serde_json::to_string_pretty(&value)? + "\n"),
+                    ConfigFormat::Yaml => Ok(serde_yaml::to_string(&value)?),
+                    _ => {
+                        let edits: Vec<String> = ops.iter().map(|op| format!("  {}", op)).collect();
+                        anyhow::bail!(
+                            "{} can't be rewritten automatically, edit it by hand:\n{}",
+                            path.display(),
+                            edits.join("\n")
+                        )
+                    }
+                }
+            }
+        }
+    }
+
+    /// Helper: Apply a migration edit to a TOML document
+    fn apply_migration_toml(doc: &mut toml_edit::DocumentMut, op: &MigrationOp) -> Result<()> {
+        match op {
+            MigrationOp::Move(from, to) => {
+                let Some(item) = Self::take_toml_key(doc, from) else {
+                    return Ok(());
+                };
+                Self::insert_toml_key(doc, to, item)?;
+
+                //> A table created for the move is placed after the one it came from
+                let parent = |key: &str| key.rsplit_once('.').map(|(parent, _)| parent.to_string());
+                let position =
+                    parent(from).and_then(|source| Self::toml_table_mut(doc, &source)?.position());
+                if let (Some(position), Some(target)) = (position, parent(to))
+                    && let Some(table) = Self::toml_table_mut(doc, &target)
+                    && table.position().is_none()
+                {
+                    table.set_position(position);
+                }
+            }
+            MigrationOp::Set(key, value) => {
+                let value: toml_edit::Value = toml::Value::try_from(value)?.to_string().parse()?;
+                Self::insert_toml_key(doc, key, toml_edit::value(value))?;
+            }
+            MigrationOp::Remove(key) => {
+                Self::take_toml_key(doc, key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Helper: Standard table at a dotted key in a TOML document
+    fn toml_table_mut<'a>(
+        doc: &'a mut toml_edit::DocumentMut,
+        key: &str,
+    ) -> Option<&'a mut toml_edit::Table> {
+        key.split('.')
+            .try_fold(doc.as_table_mut(), |table, segment| {
+                table.get_mut(segment)?.as_table_mut()
+            })
+    }
+
+    /// Helper: Remove a dotted key from a TOML document, including inline tables
+    fn take_toml_key(doc: &mut toml_edit::DocumentMut, key: &str) -> Option<toml_edit::Item> {
+        let (parents, leaf) = key.rsplit_once('.').unwrap_or(("", key));
+        let mut table: &mut dyn toml_edit::TableLike = doc.as_table_mut();
+        for segment in parents.split('.').filter(|segment| !segment.is_empty()) {
+            table = table.get_mut(segment)?.as_table_like_mut()?;
+        }
+        table.remove(leaf)
+    }
+
+    /// Helper: Insert a dotted key into a TOML document, creating tables on the way
+    fn insert_toml_key(
+        doc: &mut toml_edit::DocumentMut,
+        key: &str,
+        item: toml_edit::Item,
+    ) -> Result<()> {
+        let segments: Vec<&str> = key.split('.').collect();
+        let Some((leaf, parents)) = segments.split_last() else {
+            anyhow::bail!("Invalid config key: {}", key);
+        };
+
+        let mut table: &mut dyn toml_edit::TableLike = doc.as_table_mut();
+        for segment in parents {
+            table = table
+                .entry(segment)
+                .or_insert(toml_edit::table())
+                .as_table_like_mut()
+                .with_context(|| format!("{} is not a table", segment))?;
+        }
+        table.insert(leaf, item);
+        Ok(())
+    }
+
+    /// Helper: Print a line diff of two texts, with two lines of context around changes
+    fn print_diff(old: &str, new: &str) {
+        let old: Vec<&str> = old.lines().collect();
+        let new: Vec<&str> = new.lines().collect();
+
+        //> Longest common subsequence lengths, filled from the end
+        let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let mut lines = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                lines.push((' ', old[i]));
+                i += 1;
+                j += 1;
+            } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                lines.push(('-', old[i]));
+                i += 1;
+            } else {
+                lines.push(('+', new[j]));
+                j += 1;
+            }
+        }
+
+        let changed: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (tag, _))| *tag != ' ')
+            .map(|(index, _)| index)
+            .collect();
+        let mut last: Option<usize> = None;
+        for (index, (tag, line)) in lines.iter().enumerate() {
+            if !changed.iter().any(|change| change.abs_diff(index) <= 2) {
+                continue;
+            }
+            if last.is_some_and(|last| index > last + 1) {
+                println!("{}", "  ...".dimmed());
+            }
+            last = Some(index);
+            match tag {
+                '-' => println!("{}", format!("- {}", line).red()),
+                '+' => println!("{}", format!("+ {}", line).green()),
+                _ => println!("  {}", line),
+            }
+        }
+    }
+
+    /// Helper: Strict checks for one layer, formatted as `file:line: key: message`
+    fn strict_config_issues(layer: &ConfigLayer) -> Result<Vec<String>> {
+        let Some(path) = &layer.path else {
+            return Ok(Vec::new());
+        };
+
+        let schema = serde_json::to_value(schemars::schema_for!(DotsConfig))?;
+        let mut problems = Vec::new();
+        Self::check_schema(&layer.value, &schema, &schema, "", &mut problems);
+
+        if let Some(patterns) = Self::config_lookup(&layer.value, "excludes.patterns")
+            .and_then(|patterns| patterns.as_array())
+        {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if let Some(Err(e)) = pattern.as_str().map(Regex::new) {
+                    let message = e.to_string();
+                    let reason = message.lines().last().unwrap_or_default().trim();
+                    problems.push((
+                        format!("excludes.patterns[{}]", i),
+                        format!(
+                            "invalid regex {}: {}",
+                            pattern,
+                            reason.trim_start_matches("error: ")
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let content = fs::read_to_string(path).unwrap_or_default();
+        let lines = Self::config_key_lines(path, &content);
+
+        Ok(problems
+            .into_iter()
+            .map(|(key, message)| {
+                //> Fall back to the nearest parent key that has a known location
+                let mut lookup = key.as_str();
+                let line = loop {
+                    if let Some(line) = lines.get(lookup) {
+                        break Some(*line);
+                    }
+                    match lookup.rfind(['.', '[']) {
+                        Some(index) => lookup = &lookup[..index],
+                        None => break None,
+                    }
+                };
+
+                match line {
+                    Some(line) => format!("{}:{}: {}: {}", path.display(), line, key, message),
+                    None => format!("{}: {}: {}", path.display(), key, message),
+                }
+            })
+            .collect())
+    }
+
+    /// Helper: Check a value against a JSON Schema node, collecting `(key, message)` pairs
+    fn check_schema(
+        value: &serde_json::Value,
+        root: &serde_json::Value,
+        schema: &serde_json::Value,
+        path: &str,
+        problems: &mut Vec<(String, String)>,
+    ) {
+        use serde_json::Value;
+
+        let Some(schema) = schema.as_object() else {
+            return; // `true` accepts anything
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.trim_start_matches("#/definitions/");
+            if let Some(target) = root.get("definitions").and_then(|defs| defs.get(name)) {
+                Self::check_schema(value, root, target, path, problems);
+            }
+            return;
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for branch in all {
+                Self::check_schema(value, root, branch, path, problems);
+            }
+        }
+
+        let any = schema
+            .get("anyOf")
+            .or_else(|| schema.get("oneOf"))
+            .and_then(Value::as_array);
+        if let Some(branches) = any {
+            let candidates: Vec<&Value> = branches
+                .iter()
+                .filter(|branch| value.is_null() || branch.get("type") != Some(&"null".into()))
+                .collect();
+
+            //> A single non-null branch (an Option) reports its own nested problems
+            if let [branch] = candidates.as_slice() {
+                Self::check_schema(value, root, branch, path, problems);
+            } else if !candidates.iter().any(|branch| {
+                let mut scratch = Vec::new();
+                Self::check_schema(value, root, branch, path, &mut scratch);
+                scratch.is_empty()
+            }) {
+                problems.push((path.to_string(), format!("unexpected value {}", value)));
+            }
+            return;
+        }
+
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                let options: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+                problems.push((
+                    path.to_string(),
+                    format!("expected one of {}, found {}", options.join(", "), value),
+                ));
+            }
+            return;
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            let found = match value {
+                Value::Null => "null",
+                Value::Bool(_) => "boolean",
+                Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            };
+            let matches = types
+                .iter()
+                .any(|t| *t == found || (*t == "number" && found == "integer"));
+            if !types.is_empty() && !matches {
+                problems.push((
+                    path.to_string(),
+                    format!("expected {}, found {} {}", types.join(" or "), found, value),
+                ));
+                return;
+            }
+        }
+
+        match value {
+            Value::Object(map) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let additional = schema.get("additionalProperties");
+
+                for (key, child) in map {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+
+                    match (properties.and_then(|p| p.get(key)), additional) {
+                        (Some(child_schema), _) => {
+                            Self::check_schema(child, root, child_schema, &child_path, problems)
+                        }
+                        (None, Some(Value::Object(_))) => Self::check_schema(
+                            child,
+                            root,
+                            additional.unwrap_or(&Value::Bool(true)),
+                            &child_path,
+                            problems,
+                        ),
+                        (None, Some(Value::Bool(true))) => {}
+                        (None, _) if properties.is_some() => {
+                            problems.push((child_path, "unknown key".to_string()))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        let item_path = format!("{}[{}]", path, i);
+                        Self::check_schema(item, root, item_schema, &item_path, problems);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Helper: Map dotted keys to 1-based line numbers in a config file
+    fn config_key_lines(path: &Path, content: &str) -> HashMap<String, usize> {
+        let mut lines = HashMap::new();
+        let line_of =
+            |offset: usize| content[..offset.min(content.len())].matches('\n').count() + 1;
+
+        match ConfigFormat::from_path(path) {
+            ConfigFormat::Toml => {
+                if let Ok(doc) = toml_edit::ImDocument::parse(content) {
+                    Self::collect_toml_lines(doc.as_table(), "", &line_of, &mut lines);
+                }
+                return lines;
+            }
+            ConfigFormat::Rc => {
+                return Self::parse_rc_config(content)
+                    .map(|(_, lines)| lines)
+                    .unwrap_or_default();
+            }
+            ConfigFormat::Json | ConfigFormat::Yaml => {}
+        }
+
+        //> JSON and YAML carry no spans, so use the first line naming each key
+        let mut stack: Vec<(usize, String)> = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let indent = line.len() - line.trim_start().len();
+            let Some(key) = line
+                .trim_start()
+                .trim_start_matches('"')
+                .split(['"', ':', '=', ' '])
+                .next()
+                .filter(|key| !key.is_empty() && !key.starts_with(['#', '{', '}', '[', ']']))
+            else {
+                continue;
+            };
+
+            stack.retain(|(level, _)| *level < indent);
+            let full = match stack.last() {
+                Some((_, parent)) => format!("{}.{}", parent, key),
+                None => key.to_string(),
+            };
+            lines.entry(full.clone()).or_insert(index + 1);
+            stack.push((indent, full));
+        }
+        lines
+    }
+
+    /// Helper: Walk a TOML table recording where each key is defined
+    fn collect_toml_lines(
+        table: &toml_edit::Table,
+        prefix: &str,
+        line_of: &dyn Fn(usize) -> usize,
+        lines: &mut HashMap<String, usize>,
+    ) {
+        for (key, item) in table.iter() {
+            let path = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+
+            let span = table
+                .key(key)
+                .and_then(|k| k.span())
+                .or_else(|| item.span());
+            if let Some(span) = span {
+                lines.insert(path.clone(), line_of(span.start));
+            }
+
+            match item {
+                toml_edit::Item::Table(child) => {
+                    Self::collect_toml_lines(child, &path, line_of, lines)
+                }
+                toml_edit::Item::ArrayOfTables(tables) => {
+                    for (i, child) in tables.iter().enumerate() {
+                        let item_path = format!("{}[{}]", path, i);
+                        if let Some(span) = child.span() {
+                            lines.insert(item_path.clone(), line_of(span.start));
+                        }
+                        Self::collect_toml_lines(child, &item_path, line_of, lines);
+                    }
+                }
+                toml_edit::Item::Value(toml_edit::Value::Array(array)) => {
+                    for (i, value) in array.iter().enumerate() {
+                        if let Some(span) = value.span() {
+                            lines.insert(format!("{}[{}]", path, i), line_of(span.start));
+                        }
+                    }
+                }
+                toml_edit::Item::Value(toml_edit::Value::InlineTable(inline)) => {
+                    let child = inline.clone().into_table();
+                    Self::collect_toml_lines(&child, &path, line_of, lines);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Helper: Files that set a dotted key, highest precedence first
+    fn config_origins(&self, key: &str) -> Vec<String> {
+        let appended = self.config.merge.strategy_for(key) == VectorMerge::Append
+            && self
+                .config_layers
+                .iter()
+                .any(|layer| Self::config_lookup(&layer.value, key).is_some_and(|v| v.is_array()));
+
+        let mut origins = Vec::new();
+        for layer in self.config_layers.iter().rev() {
+            if Self::config_lookup(&layer.value, key).is_some() {
+                origins.push(layer.origin());
+
+                //> Appended vectors are built from every layer that sets them
+                if !appended {
+                    break;
+                }
+            }
+        }
+
+        if origins.is_empty() {
+            origins.push("built-in default".to_string());
+        }
+        origins
+    }
+
+    /// Helper: Look up a dotted key in a config value
+    fn config_lookup<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+        key.split('.')
+            .try_fold(value, |current, segment| current.get(segment))
+    }
+
+    /// Helper: Flatten a config value into dotted keys and leaf values
+    fn flatten_config(
+        value: &serde_json::Value,
+        prefix: &str,
+        entries: &mut Vec<(String, serde_json::Value)>,
+    ) {
+        match value {
+            serde_json::Value::Object(map) if !map.is_empty() => {
+                for (key, child) in map {
+                    let path = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    Self::flatten_config(child, &path, entries);
+                }
+            }
+            serde_json::Value::Null => {}
+            _ => entries.push((prefix.to_string(), value.clone())),
+        }
+    }
+
+    /// Helper: Dotted keys of the unset (null) fields that `flatten_config` skips
+    fn null_config_fields(value: &serde_json::Value, prefix: &str, entries: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, child) in map {
+                    let path = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    Self::null_config_fields(child, &path, entries);
+                }
+            }
+            serde_json::Value::Null => entries.push(prefix.to_string()),
+            _ => {}
+        }
+    }
+
+    /// Helper: Write a dotted key into a TOML config file, keeping its formatting
+    fn set_config_value(
+        &self,
+        path: &Path,
+        kind: LayerKind,
+        key: &str,
+        raw_value: &str,
+    ) -> Result<()> {
+        if ConfigFormat::from_path(path) != ConfigFormat::Toml {
+            anyhow::bail!("Only TOML config files can be edited: {}", path.display());
+        }
+
+        let original = if path.exists() {
+            Some(
+                fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config at {}", path.display()))?,
+            )
+        } else {
+            None
+        };
+
+        let mut doc: toml_edit::DocumentMut = original
+            .as_deref()
+            .unwrap_or_default()
+            .parse()
+            .with_context(|| format!("Failed to parse TOML config at {}", path.display()))?;
+
+        let value: toml_edit::Value = raw_value
+            .parse()
+            .unwrap_or_else(|_| toml_edit::Value::from(raw_value));
+
+        let segments: Vec<&str> = key.split('.').collect();
+        let Some((leaf, parents)) = segments.split_last() else {
+            anyhow::bail!("Invalid config key: {}", key);
+        };
+
+        let mut table = doc.as_table_mut();
+        for segment in parents {
+            let entry = table
+                .entry(segment)
+                .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+            table = entry
+                .as_table_mut()
+                .with_context(|| format!("{} is not a table in {}", segment, path.display()))?;
+        }
+        table.insert(leaf, toml_edit::value(value));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, doc.to_string())
+            .with_context(|| format!("Failed to write config at {}", path.display()))?;
+
+        //> Roll back if the new value no longer deserializes
+        let check = Self::parse_config_file(path).and_then(|value| {
+            let mut layers = self.config_layers.clone();
+            match layers
+                .iter_mut()
+                .find(|layer| layer.path.as_deref() == Some(path))
+            {
+                Some(layer) => layer.value = value,
+                None => {
+                    let position = layers.iter().filter(|layer| layer.kind <= kind).count();
+                    let path = Some(path.to_path_buf());
+                    layers.insert(position, ConfigLayer { kind, path, value });
+                }
+            }
+            Self::merge_config_layers(&layers)
+        });
+        if let Err(e) = check {
+            //> A file created just for this value goes again, so it can't become a new layer
+            match original {
+                Some(original) => fs::write(path, original)?,
+                None => fs::remove_file(path)?,
+            }
+            return Err(e.context(format!("Rejected {} = {}", key, raw_value)));
+        }
+
+        Ok(())
+    }
+
+    /// Handle init command: write a commented .dots.toml describing this repo
+    fn handle_init(&self, force: bool, to_stdout: bool) -> Result<()> {
+        let target = self.root.join(".dots.toml");
+        if target.exists() && !force && !to_stdout {
+            anyhow::bail!(
+                "{} already exists (use --force to overwrite or --stdout to preview)",
+                target.display()
+            );
+        }
+
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+        let list = |items: &[String]| {
+            let quoted: Vec<String> = items
+                .iter()
+                .map(|item| format!("  {},", quote(item)))
+                .collect();
+            format!("[\n{}\n]", quoted.join("\n"))
+        };
+        let git_config = |key: &str| -> String {
+            Command::new("git")
+                .arg("-C")
+                .arg(&self.root)
+                .args(["config", "--get", key])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .unwrap_or_default()
+        };
+
+        let name = self
+            .root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(default_name);
+
+        let mut out = String::new();
+        out.push_str("# dots configuration, generated by `dots init`\n");
+        out.push_str("# Run `dots config validate --strict` after editing.\n\n");
+        out.push_str(&format!("name = {}\n", quote(&name)));
+        out.push_str(&format!("version = {}\n\n", CONFIG_VERSION));
+
+        //> Identity comes from git so forks don't inherit someone else's
+        out.push_str("[git]\n");
+        out.push_str(&format!("user = {}\n", quote(&git_config("user.name"))));
+        out.push_str(&format!("email = {}\n", quote(&git_config("user.email"))));
+        match git_config("user.signingkey") {
+            key if key.is_empty() => out.push_str("# signing_key = \"\"\n"),
+            key => out.push_str(&format!("signing_key = {}\n", quote(&key))),
+        }
+        out.push_str("auto_push = false\n");
+
+        let submodules = self.read_gitmodules();
+        if submodules.is_empty() {
+            out.push_str("\n# No submodules found in .gitmodules\n");
+        }
+        for (key, submodule, url) in &submodules {
+            out.push_str(&format!("\n# {}\n", url));
+            out.push_str(&format!("[git.submodules.{}]\n", key));
+            out.push_str(&format!("path = {}\n", quote(&submodule.path)));
+            out.push_str("writable = true\n");
+            out.push_str(&format!("user = {}\n", quote(&submodule.user)));
+            if !submodule.branch.is_empty() {
+                out.push_str(&format!("branch = {}\n", quote(&submodule.branch)));
+            }
+        }
+
+        let (directories, patterns) = self.suggest_excludes();
+        out.push_str("\n# Paths skipped by search; patterns are regular expressions\n");
+        out.push_str("[excludes]\n");
+        out.push_str(&format!("directories = {}\n", list(&directories)));
+        out.push_str(&format!("patterns = {}\n", list(&patterns)));
+
+        let (filenames, priority) = self.suggest_order_files();
+        out.push_str("\n[order_files]\n");
+        out.push_str(&format!("filenames = {}\n", list(&filenames)));
+        out.push_str(&format!("priority = {}\n", list(&priority)));
+
+        let hosts = self.detect_hosts();
+        if !hosts.is_empty() {
+            out.push_str("\n# Per-host overrides; uncomment to customize\n");
+        }
+        for host in &hosts {
+            out.push_str(&format!("# [hosts.{}.options]\n", host));
+            out.push_str("# auto_confirm = false\n");
+            out.push_str("# dirty_tree = \"warn\"\n");
+            out.push_str(&format!("# [hosts.{}.hooks]\n", host));
+            out.push_str("# pre_rebuild = []\n");
+        }
+
+        if to_stdout {
+            print!("{}", out);
+            return Ok(());
+        }
+
+        fs::write(&target, out).with_context(|| format!("Failed to write {}", target.display()))?;
+        self.log_success(&format!("Wrote {}", target.display()), None);
+        if !submodules.is_empty() {
+            self.log_info(&format!("Imported {} submodule(s)", submodules.len()), None);
+        }
+        if !hosts.is_empty() {
+            self.log_info(&format!("Detected hosts: {}", hosts.join(", ")), None);
+        }
+
+        Ok(())
+    }
+
+    /// Helper: Parse .gitmodules into `(key, config, url)` entries
+    fn read_gitmodules(&self) -> Vec<(String, SubmoduleConfig, String)> {
+        let Ok(content) = fs::read_to_string(self.root.join(".gitmodules")) else {
+            return Vec::new();
+        };
+
+        let mut entries: Vec<(String, SubmoduleConfig, String)> = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix("[submodule \"") {
+                let name = header.trim_end_matches("\"]");
+                let key = name
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(name)
+                    .to_lowercase()
+                    .replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', "_");
+                let submodule = SubmoduleConfig {
+                    path: name.to_string(),
+                    writable: true,
+                    description: String::new(),
+                    user: String::new(),
+                    auto_sync: false,
+                    branch: String::new(),
+                };
+                entries.push((key, submodule, String::new()));
+                continue;
+            }
+
+            let (Some((_, submodule, url)), Some((field, value))) =
+                (entries.last_mut(), line.split_once('='))
+            else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match field.trim() {
+                "path" => submodule.path = value,
+                "branch" => submodule.branch = value,
+                "url" => {
+                    //> The owner is the path segment before the repository name
+                    submodule.user = value
+                        .trim_end_matches(".git")
+                        .rsplit(['/', ':'])
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string();
+                    *url = value;
+                }
+                _ => {}
+            }
+        }
+
+        entries
+    }
+
+    /// Helper: Host names from `nixosConfigurations`, else the directories in `HOSTS_DIR`
+    fn detect_hosts(&self) -> Vec<String> {
+        match self.host_names(false) {
+            Ok(hosts) => hosts,
+            Err(e) => {
+                self.log_debug(
+                    &format!("Reading hosts from {} instead: {:#}", HOSTS_DIR, e),
+                    None,
+                );
+                let mut hosts: Vec<String> = fs::read_dir(self.root.join(HOSTS_DIR))
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|e| e.path().is_dir())
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .collect();
+                hosts.sort();
+                hosts
+            }
+        }
+    }
+
+    /// Helper: Exclude suggestions based on what exists in the tree
+    fn suggest_excludes(&self) -> (Vec<String>, Vec<String>) {
+        let candidates = [".direnv", "node_modules", "target", "result", "archives"];
+        let mut directories: Vec<String> = vec![".git".to_string(), ".cache".to_string()];
+        for entry in WalkDir::new(&self.root)
+            .max_depth(3)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir())
+        {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if candidates.contains(&name.as_str()) && !directories.contains(&name) {
+                directories.push(name);
+            }
+        }
+
+        let patterns = [r"\.swp$", r"\.tmp$", r"\.log$", r"\.bak$", r"\.old$"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
 
-                let list_files = |dir: &Path, prefix: &str| -> Result<()> {
-                    if dir.exists() {
-                        for entry in fs::read_dir(dir)? {
-                            let entry = entry?;
-                            let path = entry.path();
-                            if path.is_file() {
-                                println!("{}{}", prefix, entry.file_name().to_string_lossy());
-                            }
-                        }
-                    }
-                    Ok(())
-                };
+        (directories, patterns)
+    }
 
-                println!("\nLogs:");
-                list_files(&self.logs_dir, "  ")?;
+    /// Helper: Order suggestions from entry-point files and the busiest top-level directories
+    fn suggest_order_files(&self) -> (Vec<String>, Vec<String>) {
+        let filenames = ["default.nix", "flake.nix", "shell.nix"]
+            .iter()
+            .filter(|name| self.root.join(name).is_file())
+            .map(|name| name.to_string())
+            .collect();
 
-                println!("\nTemp files:");
-                list_files(&self.tmp_dir, "  ")?;
-            }
-        }
+        let mut counts: Vec<(String, usize)> = fs::read_dir(&self.root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|e| e.path().is_dir() && !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| {
+                let nix_files = WalkDir::new(e.path())
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().extension().is_some_and(|ext| ext == "nix"))
+                    .count();
+                (e.file_name().to_string_lossy().to_string(), nix_files)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
 
-        Ok(())
+        (
+            filenames,
+            counts.into_iter().map(|(name, _)| name).collect(),
+        )
     }
 
     /// Generate shell completions
@@ -1925,12 +6682,17 @@ impl DotDots {
             ("interactive", "Interactive mode menu"),
             ("healthcheck", "Run system health checks"),
             ("rollback", "Rollback to previous generation"),
+            ("generations", "List, compare and prune system generations"),
+            ("logs", "Browse saved build logs"),
             ("hosts", "List all configured hosts"),
             ("info", "Show detailed host information"),
+            ("diff-hosts", "Compare two hosts' configuration"),
             ("rebuild", "Rebuild configuration"),
             ("test", "Test configuration"),
             ("boot", "Build boot configuration"),
             ("dry", "Dry build configuration"),
+            ("build", "Build several hosts in parallel"),
+            ("home", "Manage a Home Manager configuration"),
             ("update", "Update flake inputs"),
             ("clean", "Clean garbage collection"),
             ("binit", "Initialize PATH with bin directories"),
@@ -1941,8 +6703,10 @@ impl DotDots {
             ("repl", "Enter Nix REPL"),
             ("search", "Search for patterns"),
             ("cache", "Manage cache"),
+            ("config", "Inspect and edit configuration"),
             ("completions", "Generate shell completions"),
             ("list", "List all available commands"),
+            ("init", "Scaffold a .dots.toml from the repository"),
             ("help", "Show enhanced help with examples"),
         ];
 
@@ -1987,6 +6751,49 @@ impl DotDots {
         Ok(())
     }
 
+    /// Helper: Directories contributed by enabled includes, narrowed to their modules
+    fn include_roots(&self) -> Vec<PathBuf> {
+        let mut roots = Vec::new();
+
+        for include in self.config.includes.iter().filter(|i| i.enabled) {
+            let base = Self::resolve_include_path(&self.root, &include.path);
+            if !base.is_dir() {
+                continue;
+            }
+
+            if include.modules.is_empty() {
+                roots.push(base);
+                continue;
+            }
+
+            for module in &include.modules {
+                //> Modules are usually direct children, but allow shallow nesting
+                let direct = base.join(module);
+                let found = if direct.is_dir() {
+                    Some(direct)
+                } else {
+                    WalkDir::new(&base)
+                        .max_depth(3)
+                        .into_iter()
+                        .filter_map(|e| e.ok())
+                        .find(|e| e.file_type().is_dir() && e.file_name() == module.as_str())
+                        .map(|e| e.into_path())
+                };
+
+                match found {
+                    Some(dir) => roots.push(dir),
+                    None if self.verbose => self.log_debug(
+                        &format!("Include module not found: {}/{}", include.path, module),
+                        None,
+                    ),
+                    None => {}
+                }
+            }
+        }
+
+        roots
+    }
+
     /// Helper: Find bin directories
     fn find_bin_directories(&self) -> Result<Vec<PathBuf>> {
         let mut bin_dirs = Vec::new();
@@ -2027,7 +6834,22 @@ impl DotDots {
 
         visit_dirs(&self.root, &mut bin_dirs, 0)?;
 
-        Ok(bin_dirs)
+        //> Included trees contribute their own bin directories
+        for include_root in self.include_roots() {
+            if include_root.file_name().and_then(|n| n.to_str()) == Some("bin") {
+                bin_dirs.push(include_root.clone());
+            }
+            visit_dirs(&include_root, &mut bin_dirs, 0)?;
+        }
+
+        let mut unique = Vec::new();
+        for dir in bin_dirs {
+            if !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+
+        Ok(unique)
     }
 
     /// Helper: Execute nix eval command
@@ -2035,6 +6857,7 @@ impl DotDots {
         let output = Command::new("nix")
             .args(["eval", "--impure", "--expr", expr, "--json"])
             .env("NIX_CONFIG", "experimental-features = nix-command flakes")
+            .current_dir(&self.root)
             .output()
             .context("Failed to execute nix eval")?;
 
@@ -2043,10 +6866,32 @@ impl DotDots {
                 serde_json::from_slice(&output.stdout).context("Failed to parse JSON output")?;
             Ok(json)
         } else {
-            anyhow::bail!("Nix error: {}", String::from_utf8_lossy(&output.stderr));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.trim().is_empty() {
+                anyhow::bail!(
+                    "nix eval failed with exit code {} and no output",
+                    output.status.code().unwrap_or(1)
+                );
+            }
+            anyhow::bail!("{}", self.summarize_nix_error(&stderr));
+        }
+    }
+
+    /// Helper: Compact diagnostic for Nix stderr, or the stderr itself if it has no error
+    fn summarize_nix_error(&self, stderr: &str) -> String {
+        match NixDiagnostic::parse(stderr, &self.root) {
+            Some(diagnostic) => diagnostic.render(self.verbose),
+            None => stderr.trim().to_string(),
         }
     }
 
+    /// Helper: Quote a string as a Nix string literal
+    fn nix_string(value: &str) -> String {
+        serde_json::Value::String(value.to_string())
+            .to_string()
+            .replace("${", "\\${")
+    }
+
     /// Cached nix evaluation with TTL
     fn nix_eval_cached(
         &self,
@@ -2109,7 +6954,7 @@ impl DotDots {
         }
 
         let mut process = Command::new("sh");
-        process.arg("-c").arg(cmd);
+        process.arg("-c").arg(cmd).env("DOTS", &self.root);
 
         if let Some(dir) = dir {
             process.current_dir(dir);
@@ -2122,29 +6967,266 @@ impl DotDots {
             .status()
             .with_context(|| format!("Failed to execute {}", name))?;
 
-        if status.success() {
-            Ok(())
+        self.check_status(status, name)
+    }
+
+    /// Execute command with optional dry-run
+    fn execute_safe(&self, cmd: &str, name: &str, is_destructive: bool) -> Result<bool> {
+        if is_destructive && !self.confirm_destructive(cmd)? {
+            return Ok(false);
+        }
+
+        self.execute(cmd, name, Some(&self.root))?;
+        Ok(true)
+    }
+
+    /// Send a desktop notification that an operation finished, if enabled for it
+    ///
+    /// Only fires for actions opted into under `[options.notify]` that ran for
+    /// at least `min_duration` seconds, and only inside a graphical session.
+    fn notify_done<T>(&self, action: &str, started: Instant, summary: &str, result: &Result<T>) {
+        let notify = &self.config.options.notify;
+        let enabled = match action {
+            "rebuild" => notify.rebuild,
+            "update" => notify.update,
+            "check" => notify.check,
+            "build" => notify.build,
+            _ => false,
+        };
+        let elapsed = started.elapsed();
+        if !enabled || elapsed.as_secs() < notify.min_duration {
+            return;
+        }
+        let Some(protocol) = Self::graphical_session() else {
+            self.log_debug("No graphical session, skipping notification", None);
+            return;
+        };
+
+        let seconds = elapsed.as_secs();
+        let took = if seconds >= 60 {
+            format!("{}m {}s", seconds / 60, seconds % 60)
         } else {
-            anyhow::bail!(
-                "Command failed with exit code: {}",
-                status.code().unwrap_or(1)
-            );
+            format!("{}s", seconds)
+        };
+        let (urgency, title, body) = match result {
+            Ok(_) => (
+                "normal",
+                format!("{} finished", summary),
+                format!("Took {}", took),
+            ),
+            Err(e) => (
+                "critical",
+                format!("{} failed", summary),
+                format!("{:#}\nAfter {}", e, took),
+            ),
+        };
+
+        let sent = Command::new("notify-send")
+            .args(["--app-name=dots", "--urgency", urgency, &title, &body])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match sent {
+            Ok(status) if status.success() => self.log_debug(
+                &format!("Sent {} notification via {}", action, protocol),
+                None,
+            ),
+            _ => self.log_debug(
+                "Could not send a notification (is notify-send installed?)",
+                None,
+            ),
         }
     }
 
-    /// Execute command with optional dry-run
-    fn execute_safe(&self, cmd: &str, name: &str, is_destructive: bool) -> Result<()> {
-        if is_destructive && !self.config.options.auto_confirm {
-            println!("\n{}", "⚠️  This operation will:".yellow().bold());
-            println!("   {}", cmd.white());
+    /// Helper: Display protocol of the graphical session, if there is one
+    ///
+    /// Uses `session_environment` and `session_protocol`, ported from
+    /// `Libraries/rust/is_graphical_env.rs`. There any non-tty session with a
+    /// protocol counts, but the last fallbacks (`$TTY`, `$TERM`) name a terminal
+    /// rather than a display, so a session only known through them (plain ssh)
+    /// gets no notification.
+    fn graphical_session() -> Option<String> {
+        if Self::session_environment() == "non-graphical TTY" {
+            return None;
+        }
 
-            if !self.confirm("Continue?")? {
-                self.log_info("Cancelled", None);
-                return Ok(());
+        let protocol = Self::session_protocol();
+        let is_display = protocol == "Wayland"
+            || protocol == "X11"
+            || (cfg!(target_os = "windows") && protocol == env::consts::OS);
+        is_display.then_some(protocol)
+    }
+
+    /// Helper: Kind of session, as `get_environment` in `is_graphical_env.rs`
+    fn session_environment() -> &'static str {
+        if env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "tty") {
+            return "non-graphical TTY";
+        }
+
+        let display = env::var("WAYLAND_DISPLAY")
+            .ok()
+            .or_else(|| env::var("DISPLAY").ok());
+        if display.is_some_and(|display| !display.is_empty()) {
+            return "graphical Unix";
+        }
+
+        if env::consts::OS == "windows" {
+            return "graphical Windows";
+        }
+
+        "graphical"
+    }
+
+    /// Helper: Session protocol, as `get_protocol` in `is_graphical_env.rs`
+    fn session_protocol() -> String {
+        if env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "tty") {
+            return env::var("TTY").unwrap_or_default();
+        }
+
+        if env::var("WAYLAND_DISPLAY").is_ok_and(|display| !display.is_empty()) {
+            return "Wayland".to_string();
+        }
+
+        if env::var("DISPLAY").is_ok_and(|display| !display.is_empty()) {
+            return "X11".to_string();
+        }
+
+        if cfg!(target_os = "windows") {
+            return env::consts::OS.to_string();
+        }
+
+        env::var("TERM").unwrap_or_default()
+    }
+
+    /// Helper: Show a destructive command and ask before running it
+    fn confirm_destructive(&self, cmd: &str) -> Result<bool> {
+        if self.config.options.auto_confirm {
+            return Ok(true);
+        }
+
+        println!("\n{}", "⚠️  This operation will:".yellow().bold());
+        println!("   {}", cmd.white());
+
+        if !self.confirm("Continue?")? {
+            self.log_info("Cancelled", None);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Execute a NixOS build, teeing its output into a build log under `logs_dir`
+    fn execute_logged(&self, cmd: &str, name: &str, action: &str, host: &str) -> Result<()> {
+        use std::io::Read;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        if self.verbose && !self.quiet {
+            self.log_debug(&format!("Executing: {}", cmd), None);
+        }
+
+        let dir = self.logs_dir.join("builds");
+        fs::create_dir_all(&dir)?;
+        let started = Local::now();
+        let id = format!("{}-{}-{}", started.format("%Y%m%d-%H%M%S"), host, action);
+        let path = dir.join(format!("{}.log", id));
+
+        let mut file = fs::File::create(&path)
+            .with_context(|| format!("Failed to create build log {}", path.display()))?;
+        writeln!(file, "# host: {}", host)?;
+        writeln!(file, "# action: {}", action)?;
+        writeln!(file, "# revision: {}", self.git_revision())?;
+        writeln!(file, "# command: {}", cmd)?;
+        writeln!(file, "# started: {}", started.to_rfc3339())?;
+        let file = Arc::new(Mutex::new(file));
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .env("DOTS", &self.root)
+            .current_dir(&self.root)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute {}", name))?;
+
+        let tee = |mut reader: Box<dyn Read + Send>, to_stderr: bool| {
+            let file = Arc::clone(&file);
+            thread::spawn(move || {
+                let mut buffer = [0u8; 8192];
+                while let Ok(read) = reader.read(&mut buffer) {
+                    if read == 0 {
+                        break;
+                    }
+                    let chunk = &buffer[..read];
+                    let _ = if to_stderr {
+                        io::stderr().write_all(chunk)
+                    } else {
+                        io::stdout()
+                            .write_all(chunk)
+                            .and_then(|_| io::stdout().flush())
+                    };
+                    if let Ok(mut file) = file.lock() {
+                        let _ = file.write_all(chunk);
+                    }
+                }
+            })
+        };
+        let readers = [
+            child
+                .stdout
+                .take()
+                .map(|stdout| tee(Box::new(stdout), false)),
+            child
+                .stderr
+                .take()
+                .map(|stderr| tee(Box::new(stderr), true)),
+        ];
+
+        let status = child.wait()?;
+        for reader in readers.into_iter().flatten() {
+            let _ = reader.join();
+        }
+
+        if let Ok(mut file) = file.lock() {
+            writeln!(file, "\n# exit: {}", status.code().unwrap_or(1))?;
+            writeln!(
+                file,
+                "# duration: {:.1}s",
+                (Local::now() - started).num_milliseconds() as f64 / 1000.0
+            )?;
+        }
+
+        if !status.success() {
+            let output = fs::read_to_string(&path).unwrap_or_default();
+            if let Some(diagnostic) = NixDiagnostic::parse(&output, &self.root) {
+                eprintln!("\n{}", diagnostic.render(self.verbose));
             }
+            self.log_info(&format!("Build log: dots logs show {}", id), None);
         }
+        self.check_status(status, name)
+    }
 
-        self.execute(cmd, name, None)
+    /// Helper: Short git revision of the repository, marked when the tree is dirty
+    fn git_revision(&self) -> String {
+        let revision = Command::new("git")
+            .args([
+                "-C",
+                &self.root.to_string_lossy(),
+                "rev-parse",
+                "--short",
+                "HEAD",
+            ])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        match self.get_git_changes(&self.root) {
+            Ok(changes) if changes > 0 => format!("{}-dirty", revision),
+            _ => revision,
+        }
     }
 
     /// Helper: Check if path should be excluded
@@ -2271,8 +7353,27 @@ impl DotDots {
         Ok(())
     }
 
+    /// Preference governing a log level; debug output also appears with --verbose
+    fn preference(&self, level: LogLevel) -> Preference {
+        let logging = &self.config.logging;
+        match level {
+            LogLevel::Debug => Preference::parse(&logging.debug, Preference::SilentlyContinue),
+            LogLevel::Success | LogLevel::Info => {
+                Preference::parse(&logging.information, Preference::Continue)
+            }
+            LogLevel::Warn => Preference::parse(&logging.warning, Preference::Continue),
+            LogLevel::Error => Preference::parse(&logging.error_action, Preference::Stop),
+        }
+    }
+
     fn log_to_console(&self, msg: &str, style: ConsoleStyle<'_>) {
-        if self.quiet || (matches!(style.level, LogLevel::Debug) && !self.verbose) {
+        let hidden = match self.preference(style.level) {
+            Preference::SilentlyContinue => {
+                !(matches!(style.level, LogLevel::Debug) && self.verbose)
+            }
+            _ => false,
+        };
+        if self.quiet || hidden {
             return;
         }
 
@@ -2354,8 +7455,12 @@ impl DotDots {
         );
     }
 
-    fn log_warn(&self, msg: &str, custom_icon: Option<&'static str>) {
+    /// Show a warning, then apply `logging.warning` (see `enforce_preference`)
+    fn log_warn(&self, msg: &str, custom_icon: Option<&'static str>) -> Result<()> {
         let _ = self.log_to_file("WARN", msg);
+        if self.preference(LogLevel::Warn) == Preference::Stop {
+            anyhow::bail!("{}", msg);
+        }
         self.log_to_console(
             msg,
             ConsoleStyle {
@@ -2367,48 +7472,120 @@ impl DotDots {
                 colorize: |s| s.yellow(),
             },
         );
+        self.enforce_preference(LogLevel::Warn, msg)
     }
 
-    fn log_error(&self, msg: &str, custom_icon: Option<&'static str>) {
+    /// Show an error, then apply `logging.error_action` (see `enforce_preference`)
+    fn log_error(&self, msg: &str, custom_icon: Option<&'static str>) -> Result<()> {
         let _ = self.log_to_file("ERROR", msg);
-        self.log_to_console(
-            msg,
-            ConsoleStyle {
-                level: LogLevel::Error,
-                icon: custom_icon,
-                leading: "",
-                trailing: "",
-                use_stderr: true,
-                colorize: |s| s.red().bold(),
-            },
-        );
+        if self.preference(LogLevel::Error) == Preference::Stop {
+            anyhow::bail!("{}", msg);
+        }
+        self.log_to_console(msg, Self::error_style(custom_icon));
+        self.enforce_preference(LogLevel::Error, msg)
+    }
+
+    /// Show an error without applying `error_action`
+    ///
+    /// For reports that list every failure before failing once at the end.
+    fn show_error(&self, msg: &str) {
+        let _ = self.log_to_file("ERROR", msg);
+        self.log_to_console(msg, Self::error_style(None));
+    }
+
+    /// Apply Inquire after a warning or error has been shown
+    ///
+    /// Stop is handled by the loggers themselves, which hand the message back
+    /// as the error instead of showing it twice. Nothing here exits: callers
+    /// use `?` to abandon whatever the message was about.
+    fn enforce_preference(&self, level: LogLevel, msg: &str) -> Result<()> {
+        if self.preference(level) == Preference::Inquire && !self.confirm("Continue?")? {
+            anyhow::bail!("Cancelled after: {}", msg);
+        }
+        Ok(())
+    }
+
+    fn error_style(custom_icon: Option<&'static str>) -> ConsoleStyle<'static> {
+        ConsoleStyle {
+            level: LogLevel::Error,
+            icon: custom_icon,
+            leading: "",
+            trailing: "",
+            use_stderr: true,
+            colorize: |s| s.red().bold(),
+        }
     }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let dots = DotDots::new(cli.verbose, cli.quiet, cli.icons)?;
+    let lenient_config = matches!(cli.command, Some(Commands::Config { .. }));
+
+    //> Host profiles follow the host being built, not just the machine we're on
+    let target_host = match &cli.command {
+        Some(Commands::Rebuild { host, .. })
+        | Some(Commands::Test { host, .. })
+        | Some(Commands::Boot { host, .. }) => host.clone(),
+        _ => None,
+    };
+
+    let dots = DotDots::new(
+        cli.verbose,
+        cli.quiet,
+        cli.icons,
+        lenient_config,
+        target_host.as_deref(),
+        cli.root.as_deref(),
+        matches!(cli.command, Some(Commands::Init { .. })),
+    )?;
 
     match cli.command {
         None | Some(Commands::Help) => dots.show_help(),
 
         Some(Commands::Interactive) => dots.interactive_mode(),
         Some(Commands::Healthcheck) => dots.handle_healthcheck(),
-        Some(Commands::Rollback { execute }) => dots.handle_rollback(execute),
+        Some(Commands::Rollback { execute, to, pick }) => dots.handle_rollback(execute, to, pick),
+        Some(Commands::Generations { action }) => dots.handle_generations(action.as_ref()),
+        Some(Commands::Logs { action }) => dots.handle_logs(action.as_ref()),
 
-        Some(Commands::Hosts) => dots.list_hosts(),
+        Some(Commands::Hosts { json }) => dots.list_hosts(json),
         Some(Commands::Info {
             host,
             json,
             detailed,
-        }) => dots.show_host_info(host.as_deref(), json, detailed),
+            attrs,
+        }) => dots.show_host_info(host.as_deref(), json, detailed, &attrs),
+        Some(Commands::DiffHosts {
+            host_a,
+            host_b,
+            attrs,
+            json,
+        }) => dots.handle_diff_hosts(&host_a, &host_b, &attrs, json),
         Some(Commands::Rebuild {
             host,
             execute,
             command,
-        }) => dots.handle_rebuild(host.as_deref(), execute, command),
-        Some(Commands::Test { host, execute }) => dots.handle_test(host.as_deref(), execute),
-        Some(Commands::Boot { host, execute }) => dots.handle_boot(host.as_deref(), execute),
+            deploy,
+            dirty,
+        }) => dots.handle_rebuild(host.as_deref(), execute, command, &deploy, &dirty),
+        Some(Commands::Test {
+            host,
+            execute,
+            deploy,
+            dirty,
+        }) => dots.handle_test(host.as_deref(), execute, &deploy, &dirty),
+        Some(Commands::Boot {
+            host,
+            execute,
+            deploy,
+            dirty,
+        }) => dots.handle_boot(host.as_deref(), execute, &deploy, &dirty),
+        Some(Commands::Build { all, hosts, jobs }) => dots.handle_build(all, &hosts, jobs),
+        Some(Commands::Home {
+            action,
+            target,
+            execute,
+        }) => dots.handle_home(action, target.as_deref(), execute),
         Some(Commands::Dry { host, verbose }) => dots.handle_dry(host.as_deref(), verbose),
         Some(Commands::Update { execute, input }) => dots.handle_update(execute, input.as_deref()),
         Some(Commands::Clean {
@@ -2438,9 +7615,414 @@ fn main() -> Result<()> {
             limit,
         }) => dots.handle_search(&pattern, insensitive, file_type.as_deref(), limit),
         Some(Commands::Cache { action }) => dots.handle_cache(&action),
+        Some(Commands::Config { action }) => dots.handle_config(&action),
         Some(Commands::Completions { shell, output }) => {
             dots.handle_completions(shell, output.as_deref())
         }
         Some(Commands::List { json, names }) => dots.list_commands(json, names),
+        Some(Commands::Init { force, stdout }) => dots.handle_init(force, stdout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdfghijklmnpqrsvwxyz";
+
+    /// Helper: Fresh scratch directory for one test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dots-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rc_config_reads_sections_dotted_keys_and_legacy_names() {
+        let content = r#"# migrated from the shell version
+export DOTS_OPTIONS_AUTO_CONFIRM=1
+name = "dotDots"
+options.jobs = 4
+
+[git]
+auto_push = no
+user = craole   # trailing comment
+
+[excludes]
+patterns = [ "tmp", 'bak' ]
+"#;
+        let (value, lines) = DotDots::parse_rc_config(content).unwrap();
+
+        assert_eq!(value["options"]["auto_confirm"], true);
+        assert_eq!(value["options"]["jobs"], 4);
+        assert_eq!(value["name"], "dotDots");
+        assert_eq!(value["git"]["auto_push"], false);
+        assert_eq!(value["git"]["user"], "craole");
+        assert_eq!(
+            value["excludes"]["patterns"],
+            serde_json::json!(["tmp", "bak"])
+        );
+        assert_eq!(lines["options.auto_confirm"], 2);
+        assert_eq!(lines["git"], 6);
+        assert_eq!(lines["git.user"], 8);
+    }
+
+    #[test]
+    fn rc_config_rejects_malformed_lines() {
+        let error = DotDots::parse_rc_config("name = x\n[git\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+        assert!(DotDots::parse_rc_config("just some words").is_err());
+        assert!(DotDots::parse_rc_config("DOTS_NOT_A_SETTING=1").is_err());
+        assert!(DotDots::parse_rc_config("bad key = 1").is_err());
+    }
+
+    #[test]
+    fn rc_values_infer_their_type() {
+        use serde_json::json;
+
+        let parse = |raw: &str| DotDots::parse_rc_value(raw).unwrap();
+        assert_eq!(parse("'quoted # kept'"), json!("quoted # kept"));
+        assert_eq!(parse("\"double\""), json!("double"));
+        assert_eq!(parse("plain # comment"), json!("plain"));
+        assert_eq!(parse("on"), json!(true));
+        assert_eq!(parse("No"), json!(false));
+        assert_eq!(parse("42"), json!(42));
+        assert_eq!(parse("3.5"), json!(3.5));
+        assert_eq!(parse("[1, two, 'three']"), json!([1, "two", "three"]));
+        assert_eq!(parse("[]"), json!([]));
+        assert!(DotDots::parse_rc_value("\"open").is_err());
+        assert!(DotDots::parse_rc_value("[a, b").is_err());
+    }
+
+    #[test]
+    fn deep_merge_replaces_or_appends_vectors_per_field() {
+        let mut base = serde_json::json!({
+            "excludes": { "patterns": ["a", "b"], "directories": [".git"] },
+            "git": { "user": "craole", "auto_push": true },
+        });
+        let overlay = serde_json::json!({
+            "excludes": { "patterns": ["b", "c"], "directories": ["target"] },
+            "git": { "user": "someone" },
+            "aliases": { "up": "update" },
+        });
+        let merge = MergeOptions {
+            vectors: VectorMerge::Replace,
+            overrides: HashMap::from([("excludes.patterns".to_string(), VectorMerge::Append)]),
+        };
+
+        DotDots::deep_merge(&mut base, &overlay, "", &merge);
+
+        assert_eq!(
+            base["excludes"]["patterns"],
+            serde_json::json!(["a", "b", "c"])
+        );
+        assert_eq!(
+            base["excludes"]["directories"],
+            serde_json::json!(["target"])
+        );
+        assert_eq!(base["git"]["user"], "someone");
+        assert_eq!(base["git"]["auto_push"], true);
+        assert_eq!(base["aliases"]["up"], "update");
+    }
+
+    #[test]
+    fn env_var_names_follow_dotted_keys() {
+        assert_eq!(
+            DotDots::env_var_name("options.auto_confirm"),
+            "DOTS_OPTIONS_AUTO_CONFIRM"
+        );
+        assert_eq!(DotDots::env_var_name("git.auto_push"), "DOTS_GIT_AUTO_PUSH");
+        assert_eq!(
+            DotDots::env_var_name("options.verbosePreference"),
+            "DOTS_OPTIONS_VERBOSE_PREFERENCE"
+        );
+        assert_eq!(
+            DotDots::env_var_name("hooks.pre-rebuild"),
+            "DOTS_HOOKS_PRE_REBUILD"
+        );
+    }
+
+    #[test]
+    fn env_layer_overrides_set_and_unset_fields() {
+        let loaded = serde_json::to_value(DotDots::default_config()).unwrap();
+        let vars = [
+            ("DOTS_OPTIONS_AUTO_CONFIRM", "yes"),
+            ("DOTS_OPTIONS_JOBS", "8"),
+            ("DOTS_GIT_SIGNING_KEY", "ABC123"),
+            ("DOTS_UNRELATED", "ignored"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let layer = DotDots::env_config_layer(&loaded, vars.into_iter())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(layer.value["options"]["auto_confirm"], true);
+        assert_eq!(layer.value["options"]["jobs"], 8);
+        assert_eq!(layer.value["git"]["signing_key"], "ABC123");
+        assert!(layer.value.get("unrelated").is_none());
+
+        let bad = [("DOTS_OPTIONS_PROGRESS".to_string(), "maybe".to_string())];
+        assert!(DotDots::env_config_layer(&loaded, bad.into_iter()).is_err());
+    }
+
+    #[test]
+    fn migration_moves_preferences_and_features() {
+        let v1 = serde_json::json!({
+            "options": { "verbosePreference": "Continue", "errorActionPreference": "Stop" },
+            "experimental_features": { "nix_command": true, "flakes": false },
+            "hosts": { "QBX": { "options": { "warningPreference": "Inquire" } } },
+        });
+
+        let ops: Vec<String> = DotDots::plan_config_migration(&v1)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            ops,
+            [
+                "move options.verbosePreference to logging.verbose",
+                "move options.errorActionPreference to logging.error_action",
+                "remove experimental_features.nix_command",
+                "remove experimental_features.flakes",
+                r#"set experimental_features.enabled = ["nix-command"]"#,
+                "move hosts.QBX.options.warningPreference to hosts.QBX.logging.warning",
+                "set version = 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn migration_leaves_current_files_alone() {
+        let current = serde_json::json!({
+            "version": CONFIG_VERSION,
+            "logging": { "verbose": "SilentlyContinue" },
+        });
+        assert!(DotDots::plan_config_migration(&current).is_empty());
+
+        let stale = serde_json::json!({ "version": "0.8" });
+        let ops: Vec<String> = DotDots::plan_config_migration(&stale)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(ops, ["set version = 2"]);
+    }
+
+    #[test]
+    fn close_matches_suggest_similar_host_names() {
+        let names: Vec<String> = ["QBX", "Victus", "victus-old", "Server"]
+            .map(String::from)
+            .to_vec();
+
+        assert_eq!(DotDots::close_matches("victsu", &names), ["Victus"]);
+        assert_eq!(DotDots::close_matches("qbx", &names), ["QBX"]);
+        assert!(DotDots::close_matches("workstation", &names).is_empty());
+
+        assert_eq!(DotDots::edit_distance("kitten", "sitting"), 3);
+        assert_eq!(DotDots::edit_distance("", "abc"), 3);
+        assert_eq!(DotDots::edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn generation_ranges_expand_and_dedupe() {
+        assert_eq!(
+            DotDots::parse_generation_range("3,5,8-10").unwrap(),
+            [3, 5, 8, 9, 10]
+        );
+        assert_eq!(
+            DotDots::parse_generation_range("10..12, 11").unwrap(),
+            [10, 11, 12]
+        );
+        assert_eq!(DotDots::parse_generation_range(" 7 ").unwrap(), [7]);
+        assert!(DotDots::parse_generation_range("5-3").is_err());
+        assert!(DotDots::parse_generation_range("latest").is_err());
+        assert!(DotDots::parse_generation_range(" , ").is_err());
+    }
+
+    #[test]
+    fn store_names_split_like_parse_drv_name() {
+        let split =
+            |name: &str| DotDots::split_store_name(&format!("/nix/store/{}-{}", HASH, name));
+
+        assert_eq!(split("hello-2.12.1"), ("hello".into(), "2.12.1".into()));
+        assert_eq!(
+            split("nixos-system-QBX-25.05.20250101.2d068ae"),
+            ("nixos-system-QBX".into(), "25.05.20250101.2d068ae".into())
+        );
+        assert_eq!(
+            split("python3.12-requests-2.31.0"),
+            ("python3.12-requests".into(), "2.31.0".into())
+        );
+        assert_eq!(
+            split("linux-6.6.42-modules-shrunk"),
+            ("linux".into(), "6.6.42-modules-shrunk".into())
+        );
+        assert_eq!(split("etc"), ("etc".into(), String::new()));
+
+        assert_eq!(DotDots::format_bytes(512), "512 B");
+        assert_eq!(DotDots::format_bytes(1536), "1.5 KiB");
+        assert_eq!(DotDots::format_bytes(120 * 1024 * 1024), "120.0 MiB");
+    }
+
+    #[test]
+    fn path_info_reads_old_and_new_json() {
+        let system = format!("/nix/store/{}-nixos-system-QBX-25.05", HASH);
+        let hello = "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.1".to_string();
+
+        //> nix >= 2.19 keys entries by path
+        let keyed = serde_json::json!({
+            &system: {
+                "ca": null,
+                "closureSize": 1_073_741_824u64,
+                "deriver": format!("/nix/store/{}-nixos-system-QBX-25.05.drv", HASH),
+                "narHash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+                "narSize": 20480,
+                "references": [&hello],
+                "registrationTime": 1_735_689_600,
+                "valid": true,
+            },
+            &hello: {
+                "closureSize": 53_248,
+                "narSize": 53_248,
+                "references": [],
+                "valid": true,
+            },
+        });
+        let info = DotDots::parse_path_info(keyed, Path::new(&system));
+        assert_eq!(info.size, 1_073_741_824);
+        assert_eq!(info.paths, [hello.as_str()]);
+
+        //> Older nix prints a list with a `path` field
+        let listed = serde_json::json!([
+            { "path": &system, "closureSize": 2048, "narSize": 1024 },
+            { "path": &hello, "closureSize": 512, "narSize": 512 },
+        ]);
+        let info = DotDots::parse_path_info(listed.clone(), Path::new(&system));
+        assert_eq!(info.size, 2048);
+        assert_eq!(info.paths, [hello.as_str()]);
+
+        //> Without the root entry the size falls back to the sum of nar sizes
+        let info = DotDots::parse_path_info(listed, Path::new("/nix/store/missing"));
+        assert_eq!(info.size, 1536);
+        assert_eq!(info.paths.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn profile_generations_follow_links() {
+        use std::os::unix::fs::symlink;
+
+        let dir = scratch("generations");
+        for (number, kernel) in [(41, "6.6.41"), (42, "6.6.42")] {
+            let generation = dir.join(format!("gen-{}", number));
+            fs::create_dir_all(generation.join("kernel-modules/lib/modules").join(kernel)).unwrap();
+            fs::write(
+                generation.join("nixos-version"),
+                format!("25.05.202501{}\n", number),
+            )
+            .unwrap();
+            symlink(&generation, dir.join(format!("system-{}-link", number))).unwrap();
+        }
+        symlink("system-42-link", dir.join("system")).unwrap();
+        fs::create_dir_all(dir.join("per-user")).unwrap();
+        symlink(dir.join("gen-41"), dir.join("system-old-link")).unwrap();
+
+        let generations = DotDots::profile_generations(&dir.join("system")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let numbers: Vec<u32> = generations.iter().map(|g| g.number).collect();
+        assert_eq!(numbers, [41, 42]);
+        assert!(!generations[0].current);
+        assert!(generations[1].current);
+        assert_eq!(
+            generations[1].nixos_version.as_deref(),
+            Some("25.05.20250142")
+        );
+        assert_eq!(generations[1].kernel.as_deref(), Some("6.6.42"));
+    }
+
+    #[test]
+    fn nix_diagnostic_finds_the_repo_frame_of_an_eval_error() {
+        let root = scratch("diagnostic");
+        fs::create_dir_all(root.join("API/nix/hosts/QBX")).unwrap();
+        fs::write(root.join("API/nix/hosts/QBX/default.nix"), "{ }\n").unwrap();
+
+        let stderr = format!(
+            r#"error:
+       … while calling the 'head' builtin
+         at /nix/store/{hash}-source/lib/attrsets.nix:1541:11:
+         1540|         || pred here (elemAt values 1) (head values) then
+         1541|           head values
+             |           ^
+         1542|         else
+
+       … while evaluating definitions from `/nix/store/{hash}-source/API/nix/hosts/QBX/default.nix':
+
+       (stack trace truncated; use '--show-trace' to show the full, detailed trace)
+
+       error: undefined variable 'pkgz'
+       at /nix/store/{hash}-source/API/nix/hosts/QBX/default.nix:12:5:
+           11|   environment.systemPackages = [
+           12|     pkgz.hello
+             |     ^
+           13|   ];
+"#,
+            hash = HASH
+        );
+        let diagnostic = NixDiagnostic::parse(&stderr, &root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(diagnostic.message, ["error: undefined variable 'pkgz'"]);
+        assert_eq!(
+            diagnostic.location,
+            Some(("API/nix/hosts/QBX/default.nix".to_string(), 12, 5))
+        );
+        assert_eq!(diagnostic.source.as_deref(), Some("pkgz.hello"));
+        assert_eq!(
+            diagnostic.traces,
+            [
+                "while calling the 'head' builtin",
+                "while evaluating definitions from `API/nix/hosts/QBX/default.nix':",
+            ]
+        );
+        assert!(diagnostic.derivation.is_none());
+    }
+
+    #[test]
+    fn nix_diagnostic_reports_the_failed_builder() {
+        let stderr = format!(
+            r#"building '/nix/store/{hash}-hello-2.12.1.drv'...
+error: builder for '/nix/store/{hash}-hello-2.12.1.drv' failed with exit code 2;
+       last 3 log lines:
+       > checking for gcc... gcc
+       > make: *** [Makefile:100: all] Error 1
+       > error: build failed
+       For full logs, run 'nix log /nix/store/{hash}-hello-2.12.1.drv'.
+error: 1 dependencies of derivation '/nix/store/{hash}-nixos-system-QBX-25.05.drv' failed to build
+"#,
+            hash = HASH
+        );
+        let diagnostic = NixDiagnostic::parse(&stderr, Path::new("/nonexistent")).unwrap();
+
+        assert_eq!(diagnostic.message.len(), 1);
+        assert!(diagnostic.message[0].starts_with("error: builder for"));
+        assert_eq!(diagnostic.derivation.as_deref(), Some("hello-2.12.1"));
+        assert_eq!(
+            diagnostic.log_lines,
+            [
+                "checking for gcc... gcc",
+                "make: *** [Makefile:100: all] Error 1",
+                "error: build failed",
+            ]
+        );
+        assert_eq!(
+            diagnostic.log_hint,
+            Some(format!("nix log /nix/store/{}-hello-2.12.1.drv", HASH))
+        );
+        assert!(diagnostic.location.is_none());
+
+        assert!(NixDiagnostic::parse("warning: Git tree is dirty\n", Path::new("/")).is_none());
     }
 }
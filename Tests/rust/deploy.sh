@@ -0,0 +1,111 @@
+#!/bin/sh
+# Check the ssh deploy path of `dots rebuild` against stub ssh/nixos-rebuild
+#
+# Usage: Tests/rust/deploy.sh
+# Set DOTS_BIN to a built dots binary to skip compiling through rust-script.
+set -eu
+
+repo=$(cd "$(dirname "$0")/../.." && pwd)
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+# Stubs record what they were asked to do instead of touching any machine
+mkdir -p "$tmp/bin" "$tmp/home" "$tmp/root"
+cat >"$tmp/bin/nix" <<'STUB'
+#!/bin/sh
+case "$*" in
+  *"attrNames"*) echo '["Local","Remote"]' ;;
+esac
+STUB
+cat >"$tmp/bin/ssh" <<'STUB'
+#!/bin/sh
+echo "$*" >>"$STUB_LOG/ssh"
+[ "${STUB_SSH_FAIL:-0}" = 1 ] && exit 255
+exit 0
+STUB
+cat >"$tmp/bin/sudo" <<'STUB'
+#!/bin/sh
+echo "$*" >>"$STUB_LOG/sudo"
+exec "$@"
+STUB
+cat >"$tmp/bin/nixos-rebuild" <<'STUB'
+#!/bin/sh
+echo "$*" >>"$STUB_LOG/nixos-rebuild"
+STUB
+chmod +x "$tmp/bin/"*
+
+printf '{ outputs = _: { }; }\n' >"$tmp/root/flake.nix"
+printf '[options]\nauto_copy = false\n' >"$tmp/home/.dots.toml"
+git -C "$tmp/root" init -q
+git -C "$tmp/root" add flake.nix
+
+export PATH="$tmp/bin:$PATH" HOME="$tmp/home" HOSTNAME=Local DOTS="$tmp/root" STUB_LOG="$tmp"
+
+dots() {
+  if [ -n "${DOTS_BIN:-}" ]; then
+    "$DOTS_BIN" "$@"
+  else
+    rust-script "$repo/Libraries/rust/.dots.rs" "$@"
+  fi
+}
+
+failed=0
+expect() {
+  if grep -qF -- "$2" "$tmp/$1" 2>/dev/null; then
+    printf 'ok   %s: %s\n' "$1" "$2"
+  else
+    printf 'FAIL %s: expected %s\n' "$1" "$2"
+    failed=1
+  fi
+}
+refuse() {
+  if [ -s "$tmp/$1" ]; then
+    printf 'FAIL %s: expected no calls, got %s\n' "$1" "$(cat "$tmp/$1")"
+    failed=1
+  else
+    printf 'ok   %s: not called\n' "$1"
+  fi
+}
+reset() { rm -f "$tmp/ssh" "$tmp/sudo" "$tmp/nixos-rebuild" "$tmp/out"; }
+
+echo "== deploy to a target host"
+reset
+yes | dots rebuild Remote --target-host deploy@remote --use-remote-sudo --execute --allow-dirty >"$tmp/out" 2>&1
+expect ssh "deploy@remote"
+expect nixos-rebuild "switch --flake .#Remote --target-host deploy@remote --use-remote-sudo"
+refuse sudo
+
+echo "== unreachable target host"
+reset
+if yes | STUB_SSH_FAIL=1 dots rebuild Remote --target-host deploy@remote --execute --allow-dirty >"$tmp/out" 2>&1; then
+  echo "FAIL rebuild succeeded with an unreachable target"
+  failed=1
+fi
+refuse nixos-rebuild
+
+echo "== build host only"
+reset
+dots rebuild Local --build-host builder --use-remote-sudo >"$tmp/out" 2>&1
+expect out "nixos-rebuild switch --flake .#Local --build-host builder"
+expect out "has no effect"
+if grep -q "^sudo \|--use-remote-sudo$" "$tmp/out"; then
+  echo "FAIL out: build-host only must run without sudo or --use-remote-sudo"
+  failed=1
+fi
+
+echo "== unreachable build host"
+reset
+if yes | STUB_SSH_FAIL=1 dots rebuild Local --build-host builder --execute --allow-dirty >"$tmp/out" 2>&1; then
+  echo "FAIL rebuild succeeded with an unreachable build host"
+  failed=1
+fi
+expect ssh "builder"
+refuse nixos-rebuild
+
+echo "== remote sudo without a remote"
+reset
+dots rebuild Local --use-remote-sudo >"$tmp/out" 2>&1
+expect out "sudo nixos-rebuild switch --flake .#Local"
+expect out "has no effect"
+
+exit "$failed"